@@ -9,6 +9,10 @@ var Streamers;
     var FULL_IMAGE_PREFIX = 0x55;
     var CAMERA_IN_USE = 0x33;
     var MAX_PACKET_SIZE = 65536;
+    // Raw 16-bit frames start with the fourcc, then the width, height and
+    // length of the samples as little endian 32-bit numbers
+    var RAW_HEADER_SIZE = 16;
+    var RAW_FORMATS = ["Y10 ", "Y12 ", "Y14 ", "Y16 ", "Z16 "];
 
     Streamers = function(options) {
         var opts = _.extend(defaults, options);
@@ -31,7 +35,7 @@ var Streamers;
         });
 
         var conn = new WebSocket(opts.server, protocol);
-        conn.binaryType = "blob";
+        conn.binaryType = "arraybuffer";
 
         var jpgToCanvas = function(jpeg, context) {
             var img_url = URL.createObjectURL(jpeg);
@@ -43,27 +47,97 @@ var Streamers;
             image.src = img_url;
         };
 
+        // The header of a raw frame, or null for anything else, like a JPEG
+        var readRawHeader = function(data) {
+            if (data.byteLength < RAW_HEADER_SIZE) {
+                return null;
+            }
+            var view = new DataView(data);
+            var fourcc = String.fromCharCode(view.getUint8(0), view.getUint8(1),
+                                             view.getUint8(2), view.getUint8(3));
+            if (!_.contains(RAW_FORMATS, fourcc)) {
+                return null;
+            }
+            return {
+                fourcc: fourcc,
+                width: view.getUint32(4, true),
+                height: view.getUint32(8, true),
+                length: view.getUint32(12, true),
+            };
+        };
+
+        // Shows raw samples in grey, stretched from the lowest to the highest
+        var rawToCanvas = function(header, parts, context) {
+            var bytes = new Uint8Array(RAW_HEADER_SIZE + header.length);
+            var at = 0;
+            _.each(parts, function(part) {
+                var part_bytes = new Uint8Array(part, 0, Math.min(part.byteLength,
+                                                                  bytes.length - at));
+                bytes.set(part_bytes, at);
+                at += part_bytes.length;
+            });
+            var view = new DataView(bytes.buffer, RAW_HEADER_SIZE);
+            var pixels = Math.min(header.width * header.height, header.length / 2);
+            var low = 65535;
+            var high = 0;
+            var i;
+            for (i = 0; i < pixels; i++) {
+                var sample = view.getUint16(i * 2, true);
+                low = Math.min(low, sample);
+                high = Math.max(high, sample);
+            }
+            var span = Math.max(high - low, 1);
+            var image = context.createImageData(header.width, header.height);
+            for (i = 0; i < pixels; i++) {
+                var level = (view.getUint16(i * 2, true) - low) * 255 / span;
+                image.data[i * 4] = image.data[i * 4 + 1] = image.data[i * 4 + 2] = level;
+                image.data[i * 4 + 3] = 255;
+            }
+            context.putImageData(image, 0, 0);
+        };
+
         var buffer = [];
+        var received = 0;
+        var raw = null;
+        var receive = function(data) {
+            if (buffer.length === 0) {
+                raw = readRawHeader(data);
+            }
+            buffer.push(data);
+            received += data.byteLength;
+            // Raw frames say how long they are, JPEGs end on a short packet
+            var done = raw ? received >= RAW_HEADER_SIZE + raw.length
+                           : data.byteLength < MAX_PACKET_SIZE;
+            if (!done) {
+                return;
+            }
+            // Whatever comes after the end of a raw frame starts the next one
+            var surplus = raw ? received - (RAW_HEADER_SIZE + raw.length) : 0;
+            var parts = buffer;
+            var header = raw;
+            buffer = [];
+            received = 0;
+            if (!paused) {
+                if (header) {
+                    _.each(contexts, rawToCanvas.bind(undefined, header, parts));
+                } else {
+                    var blob = new Blob(parts, {
+                        type: "image/jpeg"
+                    });
+                    _.each(contexts, jpgToCanvas.bind(undefined, blob));
+                }
+            }
+            if (surplus > 0) {
+                receive(data.slice(data.byteLength - surplus));
+            }
+        };
+
         conn.onmessage = function(event) {
-            if (event.data.size === 1) {
+            if (event.data.byteLength === 1) {
                 // Received special message from server
-            } else {
-                buffer.push(event.data);
-                if (event.data.size < MAX_PACKET_SIZE) {
-                    var blob;
-                    if (buffer.length > 0) {
-                        blob = new Blob(buffer, {
-                            type: "image/jpeg"
-                        });
-                        buffer = [];
-                    } else {
-                        blob = event.data;
-                    }
-                    if (!paused) {
-                        _.each(contexts, jpgToCanvas.bind(undefined, blob));
-                    }
-                }
+                return;
             }
+            receive(event.data);
         };
 
         self.photograph = function(done) {
@@ -9,7 +9,7 @@ var spawn = require("child_process").spawn;
 
 var v4l2tcp = path.join(__dirname, "./v4l2tcp/target/release/v4l2tcp");
 
-var CAMERA = "/dev/video0";
+var CAMERA = process.env.CAMERA || "/dev/video0";
 var CAM_PORT = 9997;
 var CAM_HOST = "127.0.0.1";
 var MAX_LISTENERS = 10;
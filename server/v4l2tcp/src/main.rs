@@ -3,6 +3,8 @@ extern crate mio;
 extern crate time;
 
 use std::str::FromStr;
+use std::collections::BTreeMap;
+use std::io;
 use std::io::Read;
 use std::net::SocketAddr;
 use std::io::Write;
@@ -18,13 +20,21 @@ use mio::PollOpt;
 use mio::Token;
 use mio::tcp::TcpListener;
 use mio::tcp::TcpStream;
-use v4l2_quick::{Dir, Pref, Constraints, ConfigSummary, Res};
-use v4l2_quick::{Fmt, Speed, V4l2Result, Camera};
+use v4l2_quick::{Dir, Pref, Constraints, ConfigSummary};
+use v4l2_quick::{MmapCamera, Error, CaptureSource, TestPattern};
+use v4l2_quick::{Replay, Snapshot, Presets, Profiles, FourCc, Cached, Report, Retry};
+use v4l2_quick::SettingsFile;
+use v4l2_quick::{V4l2Error, ToJpeg, Gains, Normalize, Palette, Range, Frame};
 
 const CLIENT: Token = Token(0);
 const SERVER: Token = Token(1);
 const TIMEOUT: Token = Token(2);
-const USAGE: &'static str = "<camera path> <listen addr>";
+const USAGE: &str = "[--profiles <file>] [--formats <fourcc>,...] [--quality <1-100>] \
+                              [--gains <red>,<green>,<blue>] \
+                              [--range auto|<min>,<max>] [--palette grey|iron|rainbow] [--raw16] \
+                              <camera path | pattern: | file:<recording>[@<fps>]> <listen addr> \
+                              [<presets file> [<preset>]]";
+const DUMP_USAGE: &str = "--dump <camera path>";
 
 #[derive(Debug)]
 struct Connection {
@@ -49,17 +59,39 @@ impl Connection {
     }
 }
 
+/// How frames that aren't JPEGs already get compressed
+#[derive(Clone)]
+struct Encoding {
+    // JPEG quality, from 1 to 100
+    quality: u8,
+    // White balance for raw Bayer sensors
+    gains: Gains,
+    // Colors for 16-bit greyscale, like thermal cameras send
+    normalize: Normalize,
+    // Send 16-bit greyscale as it is, for clients that read the samples
+    raw16: bool,
+}
+
 struct CameraData {
     // Like /dev/video0
     path: String,
     // Camera handle
-    handle: Option<Camera>,
+    handle: Option<ToJpeg<Box<dyn CaptureSource>>>,
+    // The driver's sequence number of the last frame sent, a gap after it
+    // means frames were dropped. Forgotten whenever streaming starts over.
+    sequence: Option<u32>,
     // Config for the fastest framerate
     fastest: ConfigSummary,
     // Config for the best quality
     best: ConfigSummary,
     // Milliseconds before next frame
     interval: u64,
+    // How to compress frames for cameras that don't send JPEGs themselves
+    encoding: Encoding,
+    // Named control settings
+    presets: Presets,
+    // The preset to put back whenever the camera is reopened
+    preset: Option<String>,
 }
 
 struct CamServer {
@@ -69,89 +101,204 @@ struct CamServer {
     timeout: Option<Timeout>,
 }
 
-impl CamServer {
-    fn new(cam_path: String, server: TcpListener) -> Result<Self, ()> {
-        // Get the camera parameters with the best quality
-        let want_quality = Constraints {
-            formats: Some(Fmt {
-                emulate: Pref::NoPreference,
-                compress: Pref::DoNotPrefer,
-                priorities: Some(vec![b"MJPG"]),
-            }),
-            resolutions: Some(Res {
-                dir: Dir::Highest,
-                limit: None,
-            }),
-            speeds: None,
-            .. Default::default()
-        };
-        // Get the configuration
-        let quality = match v4l2_quick::configure(&cam_path, want_quality) {
-            Ok(Some(config)) => config,
-            _ => return Err(()),
-        };
-        // Get the camera parameters with the fastest framerate
-        // But keep it above 640x480
-        let want_framerate = Constraints {
-            formats: Some(Fmt {
-                emulate: Pref::DoNotPrefer,
-                compress: Pref::Prefer,
-                priorities: Some(vec![b"MJPG"]),
-            }),
-            resolutions: Some(Res {
-                dir: Dir::Lowest,
-                limit: Some((640, 480)),
-            }),
-            speeds: Some(Speed {
-                dir: Dir::Highest,
-                limit: None,
-            }),
-            .. Default::default()
-        };
-        // Get the config
-        let framerate = match v4l2_quick::configure(&cam_path, want_framerate) {
-            Ok(Some(config)) => config,
-            _ => return Err(()),
+/// Opens whatever `path` names: a device like /dev/video0, a generated test
+/// pattern for `pattern:`, or a looping recording for `file:footage.mjpg@15`
+/// where the rate is optional
+fn open_camera(path: &str) -> io::Result<Box<dyn CaptureSource>> {
+    if path == "pattern:" {
+        return Ok(Box::new(TestPattern::new()));
+    }
+    if let Some(recording) = path.strip_prefix("file:") {
+        // Only take what follows an @ as the rate if it reads as one
+        let (recording, fps) = match recording.rfind('@') {
+            Some(at) => match f32::from_str(&recording[at + 1..]) {
+                Ok(fps) if fps > 0. => (&recording[..at], Some(fps)),
+                _ => (recording, None),
+            },
+            None => (recording, None),
         };
+        let mut replay = try!(Replay::open(recording));
+        replay.looping = true;
+        replay.interval = fps.map(|fps| (1000, (fps * 1000.).round() as u32));
+        return Ok(Box::new(replay));
+    }
+    // Our own buffers keep the driver's timestamps and sequence numbers
+    Ok(Box::new(try!(MmapCamera::new(path))))
+}
+
+/// Whether `path` names a device, rather than a test pattern or a recording
+fn is_device(path: &str) -> bool {
+    path != "pattern:" && !path.starts_with("file:")
+}
+
+/// Leaves out the configs the driver would quietly change, and has those
+/// left as the driver negotiates them. Only devices have a driver to ask.
+fn verified(path: &str, configs: Vec<ConfigSummary>) -> Result<Vec<ConfigSummary>, Error> {
+    if !is_device(path) {
+        return Ok(configs);
+    }
+    let mut report = Report::default();
+    let configs = try!(v4l2_quick::verify(path, configs, &mut report));
+    if configs.is_empty() {
+        return Err(Error::NoMatch(report));
+    }
+    Ok(configs)
+}
+
+/// Opens `path` like `open_camera`, compressing whatever isn't a JPEG
+/// already since that is all the client shows
+fn open_jpeg(path: &str, encoding: &Encoding) -> io::Result<ToJpeg<Box<dyn CaptureSource>>> {
+    let mut camera = ToJpeg::new(try!(open_camera(path)));
+    camera.quality = encoding.quality;
+    camera.gains = encoding.gains;
+    camera.normalize = encoding.normalize;
+    camera.raw16 = encoding.raw16;
+    Ok(camera)
+}
+
+/// Sends `frame` to the client. Raw 16-bit frames go after a header, so
+/// clients can tell them from JPEGs and know where they end: the fourcc,
+/// then the width, height and length of the samples as little endian u32s.
+fn send(stream: &mut TcpStream, frame: &Frame) -> io::Result<()> {
+    if v4l2_quick::sample_bits(frame.format).is_some() {
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&frame.format.0);
+        for &value in &[frame.resolution.0, frame.resolution.1, frame.len() as u32] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        try!(stream.write_all(&header));
+    }
+    stream.write_all(&frame[..])
+}
+
+/// The formats to stream, best first. Those after NV12 are only there for
+/// cameras without anything better: Bayer is debayered here, and 16-bit
+/// greyscale and depth are normalized or sent raw.
+const PRIORITIES: &[&str] = &["MJPG", "YUYV", "NV12", "BA81", "GBRG", "GRBG", "RGGB",
+                              "Y16", "Z16", "Y10", "Y12", "Y14"];
+
+/// What to look for when nothing else is asked for: "best" for stills,
+/// and "fast" for streaming. All but a `recording` are held to a smallest
+/// size, recordings are as big as they were recorded.
+fn default_profiles(recording: bool) -> Profiles {
+    let mut profiles = BTreeMap::new();
+    let prefer = || PRIORITIES.iter().fold(Constraints::builder(), |builder, &format| {
+        builder.prefer_format(format)
+    });
+    // Get the camera parameters with the best quality
+    profiles.insert("best".to_string(), prefer()
+        .compressed(Pref::DoNotPrefer)
+        .resolution_dir(Dir::Highest)
+        .build()
+        .expect("the best profile is consistent"));
+    // Get the camera parameters with the fastest framerate
+    let fast = prefer()
+        .emulated(Pref::DoNotPrefer)
+        .compressed(Pref::Prefer)
+        .resolution_dir(Dir::Lowest)
+        .fps_dir(Dir::Highest);
+    // But keep it above 640x480
+    let fast = if recording { fast } else { fast.min_resolution(640, 480) };
+    profiles.insert("fast".to_string(), fast.build().expect("the fast profile is consistent"));
+    Profiles(profiles)
+}
+
+impl CamServer {
+    fn new(cam_path: String, server: TcpListener, profiles: &Profiles, encoding: Encoding,
+           presets: Presets, preset: Option<String>) -> Result<Self, Error> {
+        // Get the configuration, asking the camera what it offers only once
+        let camera = Cached::new(try!(open_jpeg(&cam_path, &encoding)));
+        let quality = try!(v4l2_quick::search(&camera, profiles.0["best"].clone()));
+        let quality = try!(verified(&cam_path, quality)).remove(0);
+        // Get every config that would do, best first
+        let options = try!(v4l2_quick::search(&camera, profiles.0["fast"].clone()));
+        let options = try!(verified(&cam_path, options));
+        // Lets start with the fast camera, falling back to the next
+        // config if the camera is busy or out of bandwidth.
+        // The first try gets the camera that was searched.
+        let mut searched = Some(camera.into_inner());
+        let started = try!(v4l2_quick::start_first(|| match searched.take() {
+            Some(camera) => Ok(camera),
+            None => open_jpeg(&cam_path, &encoding).map_err(V4l2Error::from),
+        }, options, &Retry::default()));
+        for (config, err) in &started.failures {
+            writeln!(&mut stderr(), "Could not start {} {}x{}: {}", config.format,
+                     config.resolution.0, config.resolution.1, err).ok();
+        }
+        println!("Started {:?}", started.config);
+        let (mut camera, framerate) = (started.source, started.config);
+        // Drivers can pad rows out, the encoder has to skip the padding
+        camera.stride = framerate.stride.map(|stride| stride as usize);
         // Calculate how fast we should update
         let interval = framerate.interval;
         let refresh = ((interval.0 as f32 / interval.1 as f32) * 1000. + 0.5) as u64;
-        // Lets start with the fast camera
-        let mut camera = Camera::new(&cam_path).unwrap();
-        v4l2_quick::start(&mut camera, &framerate).unwrap();
         // Cache configs for faster switching
-        Ok(CamServer {
+        let mut cams = CamServer {
             server: server,
             client: None,
             timeout: None,
             camera: CameraData {
                 handle: Some(camera),
+                sequence: None,
                 fastest: framerate,
                 best: quality,
                 interval: refresh,
                 path: cam_path,
+                encoding: encoding,
+                presets: presets,
+                preset: preset,
             },
-        })
+        };
+        cams.apply_preset();
+        Ok(cams)
     }
 
-    fn camera_fast(&mut self) -> V4l2Result<()> {
+    /// Puts the active preset's controls back, reopening resets them
+    fn apply_preset(&mut self) {
+        let camera = &mut self.camera;
+        let preset = match camera.preset {
+            Some(ref name) => match camera.presets.get(name) {
+                Some(preset) => preset,
+                None => {
+                    writeln!(&mut stderr(), "No preset called {}", name).ok();
+                    return;
+                },
+            },
+            None => return,
+        };
+        if let Some(ref mut handle) = camera.handle {
+            if let Err(err) = preset.apply(handle) {
+                writeln!(&mut stderr(), "Could not apply the preset: {}", err).ok();
+            }
+        }
+    }
+
+    /// Reopens the camera with `config`, waiting out a busy device
+    fn restart(&mut self, config: ConfigSummary) -> Result<(), Error> {
         // Get rid of the old camera
         self.camera.handle = None;
-        // Make a new one with the 'fast' config
-        let mut camera = try!(Camera::new(&self.camera.path));
-        try!(v4l2_quick::start(&mut camera, &self.camera.fastest));
+        let (path, encoding) = (&self.camera.path, &self.camera.encoding);
+        let started = try!(v4l2_quick::start_first(|| {
+            open_jpeg(path, encoding).map_err(V4l2Error::from)
+        }, Some(config), &Retry::default()));
+        let mut camera = started.source;
+        camera.stride = started.config.stride.map(|stride| stride as usize);
         self.camera.handle = Some(camera);
+        self.camera.sequence = None;
+        self.apply_preset();
         Ok(())
     }
 
-    fn camera_quality(&mut self) -> V4l2Result<()> {
-        // Get rid of the old camera
-        self.camera.handle = None;
+    fn camera_fast(&mut self) -> Result<(), Error> {
         // Make a new one with the 'fast' config
-        let mut camera = try!(Camera::new(&self.camera.path));
-        try!(v4l2_quick::start(&mut camera, &self.camera.best));
-        self.camera.handle = Some(camera);
-        Ok(())
+        let config = self.camera.fastest.clone();
+        self.restart(config)
+    }
+
+    fn camera_quality(&mut self) -> Result<(), Error> {
+        // Make a new one with the 'quality' config
+        let config = self.camera.best.clone();
+        self.restart(config)
     }
 }
 
@@ -183,8 +330,9 @@ impl Handler for CamServer {
                             CLIENT,
                             EventSet::all(),
                             PollOpt::edge()).unwrap();
-                        // Save the client
+                        // Save the client, it hasn't missed anything yet
                         self.client = Some(connection);
+                        self.camera.sequence = None;
                     },
                     _ => return,
                 };
@@ -221,16 +369,21 @@ impl Handler for CamServer {
             match message.trim_right() {
                 "capture" => {
                     // Find one that has really good quality
-                    self.camera_quality().unwrap();
+                    if let Err(err) = self.camera_quality() {
+                        writeln!(&mut stderr(), "Could not switch to quality: {}", err).ok();
+                    }
                     // Get a picture from the good camera
-                    if let Ok(frame) = self.camera.handle.as_mut().unwrap().capture() {
+                    let frame = self.camera.handle.as_mut().map(|handle| handle.capture());
+                    if let Some(Ok(frame)) = frame {
                         if let Some(ref mut client) = self.client {
                             // Send the picture to the client
-                            client.stream.write_all(&frame[..]).ok();
+                            send(&mut client.stream, &frame).ok();
                         }
                     }
                     // Find the original, faster camera
-                    self.camera_fast().unwrap();
+                    if let Err(err) = self.camera_fast() {
+                        writeln!(&mut stderr(), "Could not switch back to fast: {}", err).ok();
+                    }
                 },
                 "shutdown" => {
                     // Destroy everything
@@ -244,9 +397,16 @@ impl Handler for CamServer {
                     }
                 },
                 "resume" => {
-                    // Start a new timer and capture frames
+                    // Start a new timer and capture frames, those skipped
+                    // while paused weren't dropped
+                    self.camera.sequence = None;
                     self.timeout = event_loop.timeout_ms(TIMEOUT, 0u64).ok();
                 },
+                command if command.starts_with("preset ") => {
+                    // Switch to a preset, it sticks through reopening
+                    self.camera.preset = Some(command["preset ".len()..].trim().to_string());
+                    self.apply_preset();
+                },
                 _ => return,
             };
         }
@@ -263,10 +423,18 @@ impl Handler for CamServer {
                 event_loop.timeout_ms(token, self.camera.interval).ok();
                 return;
             }
-            // Get a frame from the camera
-            if let Ok(frame) = self.camera.handle.as_mut().unwrap().capture() {
+            // Get a frame from the camera, there is none if it failed to reopen
+            let frame = self.camera.handle.as_mut().map(|handle| handle.capture());
+            if let Some(Ok(frame)) = frame {
+                if let (Some(last), Some(sequence)) = (self.camera.sequence, frame.sequence) {
+                    let dropped = sequence.saturating_sub(last).saturating_sub(1);
+                    if dropped > 0 {
+                        println!("Dropped {} frames", dropped);
+                    }
+                }
+                self.camera.sequence = frame.sequence;
                 // Send it to the client
-                if client.stream.write_all(&frame[..]).is_ok() {
+                if send(&mut client.stream, &frame).is_ok() {
                     // Guess how much longer we should wait until we go again
                     let used = time::precise_time_ns() - start;
                     let timeout = if used > self.camera.interval {
@@ -274,7 +442,14 @@ impl Handler for CamServer {
                     } else {
                         self.camera.interval - used
                     };
-                    println!("FRAME!");
+                    // How long since the driver stamped it, sending included
+                    match frame.age() {
+                        Some(latency) => {
+                            let ms = latency.as_secs() * 1000 + latency.subsec_millis() as u64;
+                            println!("FRAME! {} after {}ms", frame.sequence.unwrap_or(0), ms);
+                        },
+                        None => println!("FRAME!"),
+                    }
                     // If sending went ok, do it again soon
                     self.timeout = event_loop.timeout_ms(token, timeout).ok();
                 }
@@ -283,7 +458,47 @@ impl Handler for CamServer {
     }
 }
 
-fn start(cam_path: String, server_addr: &str) {
+fn start(cam_path: String, server_addr: &str, profiles_path: Option<String>,
+         formats: Option<Vec<FourCc>>, encoding: Encoding,
+         presets_path: Option<String>, preset: Option<String>) {
+    // Profiles in the file replace the built-in ones of the same name
+    let mut profiles = default_profiles(cam_path.starts_with("file:"));
+    if let Some(path) = profiles_path {
+        match Profiles::load(&path) {
+            Ok(loaded) => profiles.0.extend(loaded.0),
+            Err(err) => {
+                writeln!(&mut stderr(), "Could not read the profiles in {}: {}", path, err).ok();
+                exit(1);
+            },
+        }
+        // The file skips the builder, so check what it would have
+        for (name, constraints) in &profiles.0 {
+            if let Err(err) = constraints.validate() {
+                writeln!(&mut stderr(), "The {} profile in {}: {}", name, path, err).ok();
+                exit(1);
+            }
+        }
+    }
+    // Formats on the command line win over every profile
+    if let Some(formats) = formats {
+        for constraints in profiles.0.values_mut() {
+            let mut fmt = constraints.formats.take().unwrap_or_default();
+            fmt.priorities = Some(formats.clone());
+            constraints.formats = Some(fmt);
+        }
+    }
+    // Read the presets first, there's no point starting with a typo in them
+    let presets = match presets_path {
+        Some(path) => match Presets::load(&path) {
+            Ok(presets) => presets,
+            Err(err) => {
+                writeln!(&mut stderr(), "Could not read the presets in {}: {}", path, err).ok();
+                exit(1);
+            },
+        },
+        None => Presets::default(),
+    };
+
     // Create the TCP Server
     let address = SocketAddr::from_str(server_addr).unwrap();
     let server = TcpListener::bind(&address).unwrap();
@@ -295,22 +510,150 @@ fn start(cam_path: String, server_addr: &str) {
     }).unwrap();
 
     // Server
-    let mut cams = CamServer::new(cam_path, server).unwrap();
+    let cams = CamServer::new(cam_path, server, &profiles, encoding, presets, preset);
+    let mut cams = match cams {
+        Ok(cams) => cams,
+        Err(err) => {
+            writeln!(&mut stderr(), "Could not set up the camera: {}", err).ok();
+            exit(1);
+        },
+    };
 
     // Start event loop
     event_loop.register(&cams.server, SERVER).unwrap();
     event_loop.run(&mut cams).unwrap();
 }
 
+/// Prints everything the camera offers as JSON, to attach to bug reports
+fn dump(cam_path: &str) -> Result<(), Error> {
+    let snapshot = match v4l2_quick::query(cam_path) {
+        Ok(device) => Snapshot::from(device),
+        // Not a device, or not one we can query, try it as a source
+        Err(_) => try!(Snapshot::of(&try!(open_camera(cam_path)))),
+    };
+    println!("{}", snapshot.to_json());
+    Ok(())
+}
+
+/// Removes `name` from `arguments`, returning whether it was there
+fn take_flag(arguments: &mut Vec<String>, name: &str) -> bool {
+    match arguments.iter().position(|arg| arg == name) {
+        Some(at) => {
+            arguments.remove(at);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Removes `name` and the value after it from `arguments`, returning the value
+fn take_option(arguments: &mut Vec<String>, name: &str) -> Option<String> {
+    match arguments.iter().position(|arg| arg == name) {
+        Some(at) if at + 1 < arguments.len() => {
+            let value = arguments.remove(at + 1);
+            arguments.remove(at);
+            Some(value)
+        },
+        _ => None,
+    }
+}
+
 fn main() {
-    let mut arguments = args();
-    let program = arguments.next().unwrap();
+    let mut arguments: Vec<String> = args().collect();
+    let program = arguments.remove(0);
+    // Take the options out, what's left goes by position
+    let profiles = take_option(&mut arguments, "--profiles");
+    let formats = take_option(&mut arguments, "--formats").map(|list| {
+        list.split(',').map(|format| FourCc::from_str(format.trim())).collect()
+    });
+    let formats = match formats {
+        Some(Err(err)) => {
+            writeln!(&mut stderr(), "{}", err).ok();
+            exit(1);
+        },
+        Some(Ok(formats)) => Some(formats),
+        None => None,
+    };
+    let quality = match take_option(&mut arguments, "--quality").map(|q| u8::from_str(&q)) {
+        Some(Ok(quality)) if (1..=100).contains(&quality) => quality,
+        Some(_) => {
+            writeln!(&mut stderr(), "The quality is a number from 1 to 100").ok();
+            exit(1);
+        },
+        None => 85,
+    };
+    let gains = take_option(&mut arguments, "--gains").map(|list| {
+        list.split(',').map(|gain| f32::from_str(gain.trim())).collect::<Result<Vec<_>, _>>()
+    });
+    let gains = match gains {
+        Some(Ok(ref gains)) if gains.len() == 3 && gains.iter().all(|&gain| gain >= 0.) => {
+            Gains { red: gains[0], green: gains[1], blue: gains[2] }
+        },
+        Some(_) => {
+            writeln!(&mut stderr(), "The gains are three numbers, like 1.8,1,1.5").ok();
+            exit(1);
+        },
+        None => Gains::default(),
+    };
+    let range = take_option(&mut arguments, "--range").map(|range| {
+        if range == "auto" {
+            return Some(Range::Auto);
+        }
+        let ends: Vec<_> = range.split(',').map(|end| u16::from_str(end.trim())).collect();
+        match ends[..] {
+            [Ok(min), Ok(max)] if min < max => Some(Range::Fixed { min: min, max: max }),
+            _ => None,
+        }
+    });
+    let range = match range {
+        Some(Some(range)) => range,
+        Some(None) => {
+            writeln!(&mut stderr(), "The range is auto, or the raw samples at either end \
+                                     like 27000,31000").ok();
+            exit(1);
+        },
+        None => Range::Auto,
+    };
+    let palette = match take_option(&mut arguments, "--palette").map(|name| name.parse()) {
+        Some(Ok(palette)) => palette,
+        Some(Err(err)) => {
+            writeln!(&mut stderr(), "{}", err).ok();
+            exit(1);
+        },
+        None => Palette::Grey,
+    };
+    let encoding = Encoding {
+        quality: quality,
+        gains: gains,
+        normalize: Normalize { range: range, palette: palette },
+        raw16: take_flag(&mut arguments, "--raw16"),
+    };
+    let mut arguments = arguments.into_iter();
     let camera = arguments.next();
     let server = arguments.next();
     match (camera, server) {
-        (Some(c), Some(s)) => start(c, &s),
+        (Some(ref flag), Some(ref c)) if flag == "--dump" => {
+            if let Err(err) = dump(c) {
+                writeln!(&mut stderr(), "Could not query the camera: {}", err).ok();
+                exit(1);
+            }
+        },
+        (Some(c), Some(s)) => {
+            start(c, &s, profiles, formats, encoding, arguments.next(), arguments.next())
+        },
         _ => {
             writeln!(&mut stderr(), "Usage: {} {}", program, USAGE).ok();
+            writeln!(&mut stderr(), "       {} {}", program, DUMP_USAGE).ok();
+            // Save a trip to /dev by listing the cameras
+            let devices = v4l2_quick::devices().unwrap_or_default();
+            let cameras: Vec<_> = devices.iter().filter(|device| device.is_capture()).collect();
+            if !cameras.is_empty() {
+                writeln!(&mut stderr(), "Cameras:").ok();
+            }
+            for camera in cameras {
+                writeln!(&mut stderr(), "    {}  {} ({})",
+                         camera.path.display(), camera.card, camera.driver).ok();
+            }
             exit(1);
         }
     }
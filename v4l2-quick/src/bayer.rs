@@ -0,0 +1,170 @@
+use convert::{ConvertError, PixelLayout, RgbFormat};
+use fourcc::FourCc;
+
+const RED: usize = 0;
+const GREEN: usize = 1;
+const BLUE: usize = 2;
+
+/// White balance gains, each channel is multiplied by its own after
+/// demosaicing. Raw sensors come out green without them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gains {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Default for Gains {
+    fn default() -> Self {
+        Gains {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        }
+    }
+}
+
+/// The colors of the top left 2x2 block of a Bayer format,
+/// as the top row then the bottom one
+fn pattern(format: FourCc) -> Option<[usize; 4]> {
+    match format {
+        FourCc::BA81 => Some([BLUE, GREEN, GREEN, RED]),
+        FourCc::GBRG => Some([GREEN, BLUE, RED, GREEN]),
+        FourCc::GRBG => Some([GREEN, RED, BLUE, GREEN]),
+        FourCc::RGGB => Some([RED, GREEN, GREEN, BLUE]),
+        _ => None,
+    }
+}
+
+/// Whether `format` is one of the 8-bit Bayer formats `debayer` reads
+pub fn is_bayer(format: FourCc) -> bool {
+    pattern(format).is_some()
+}
+
+/// Mirrors coordinates off the edge back in, which keeps the color a
+/// neighbor would have had
+fn reflect(at: isize, len: usize) -> usize {
+    let last = len as isize - 1;
+    let at = if at < 0 { -at } else if at > last { 2 * last - at } else { at };
+    at.max(0).min(last) as usize
+}
+
+/// Demosaics an 8-bit Bayer frame laid out as `layout` to packed RGB or
+/// RGBA, bilinearly: each pixel takes the channels it lacks from the
+/// average of its nearest neighbors that have them
+pub fn debayer(data: &[u8], layout: &PixelLayout, out: RgbFormat, gains: &Gains)
+        -> Result<Vec<u8>, ConvertError> {
+    let format = layout.format;
+    let colors = try!(pattern(format).ok_or(ConvertError::Unsupported(format)));
+    let (width, height) = (layout.resolution.0 as usize, layout.resolution.1 as usize);
+    let stride = layout.stride.unwrap_or(width);
+    if stride < width {
+        return Err(ConvertError::BadStride { stride: stride, min: width });
+    }
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+    let needed = stride * (height - 1) + width;
+    if data.len() < needed {
+        return Err(ConvertError::TooShort { len: data.len(), needed: needed });
+    }
+
+    let color = |x: usize, y: usize| colors[y % 2 * 2 + x % 2];
+    let gains = [gains.red, gains.green, gains.blue];
+    let mut rgb = Vec::with_capacity(width * height * out.bytes_per_pixel());
+    for y in 0..height {
+        for x in 0..width {
+            let own = color(x, y);
+            let mut pixel = [0u8; 3];
+            for channel in 0..3 {
+                let value = if channel == own {
+                    data[y * stride + x] as f32
+                } else {
+                    // Beside first, then the corners for red and blue
+                    // at blue and red pixels
+                    let sides = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+                    let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+                    let average = |offsets: &[(isize, isize)]| {
+                        let (mut sum, mut count) = (0u32, 0u32);
+                        for &(dx, dy) in offsets {
+                            let nx = reflect(x as isize + dx, width);
+                            let ny = reflect(y as isize + dy, height);
+                            if color(nx, ny) == channel {
+                                sum += data[ny * stride + nx] as u32;
+                                count += 1;
+                            }
+                        }
+                        if count == 0 { None } else { Some(sum as f32 / count as f32) }
+                    };
+                    average(&sides).or_else(|| average(&corners)).unwrap_or(0.)
+                };
+                pixel[channel] = (value * gains[channel]).round().clamp(0., 255.) as u8;
+            }
+            rgb.extend_from_slice(&pixel);
+            if out == RgbFormat::Rgba8 {
+                rgb.push(0xFF);
+            }
+        }
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` by `height` frame in `format` of a flat color, each pixel
+    /// holding its own channel of `rgb`, padded out to `stride`
+    fn flat(format: FourCc, (width, height): (usize, usize), stride: usize, rgb: [u8; 3])
+            -> Vec<u8> {
+        let colors = pattern(format).unwrap();
+        let mut data = vec![0xEE; stride * height];
+        for y in 0..height {
+            for x in 0..width {
+                data[y * stride + x] = rgb[colors[y % 2 * 2 + x % 2]];
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn flat_colors_stay_flat() {
+        for &format in &[FourCc::BA81, FourCc::GBRG, FourCc::GRBG, FourCc::RGGB] {
+            let data = flat(format, (4, 4), 4, [200, 100, 50]);
+            let layout = PixelLayout::packed(format, (4, 4));
+            let rgb = debayer(&data, &layout, RgbFormat::Rgb8, &Gains::default()).unwrap();
+            assert_eq!(rgb, [200, 100, 50].repeat(16), "{}", format);
+        }
+    }
+
+    #[test]
+    fn gains_and_padding() {
+        let data = flat(FourCc::RGGB, (3, 2), 5, [200, 100, 50]);
+        let layout = PixelLayout { stride: Some(5), ..PixelLayout::packed(FourCc::RGGB, (3, 2)) };
+        let gains = Gains { red: 1.5, green: 1., blue: 2. };
+        let rgba = debayer(&data, &layout, RgbFormat::Rgba8, &gains).unwrap();
+        // Red saturates rather than wrapping
+        assert_eq!(rgba, [255, 100, 100, 0xFF].repeat(6));
+    }
+
+    #[test]
+    fn lone_pixel() {
+        let layout = PixelLayout::packed(FourCc::GRBG, (1, 1));
+        let rgb = debayer(&[9], &layout, RgbFormat::Rgb8, &Gains::default());
+        assert_eq!(rgb, Ok(vec![0, 9, 0]));
+    }
+
+    #[test]
+    fn errors() {
+        let gains = Gains::default();
+        let layout = PixelLayout::packed(FourCc::GREY, (2, 2));
+        assert_eq!(debayer(&[0; 4], &layout, RgbFormat::Rgb8, &gains),
+                   Err(ConvertError::Unsupported(FourCc::GREY)));
+        let layout = PixelLayout { stride: Some(1), ..PixelLayout::packed(FourCc::RGGB, (2, 2)) };
+        assert_eq!(debayer(&[0; 4], &layout, RgbFormat::Rgb8, &gains),
+                   Err(ConvertError::BadStride { stride: 1, min: 2 }));
+        let layout = PixelLayout::packed(FourCc::RGGB, (2, 2));
+        assert_eq!(debayer(&[0; 3], &layout, RgbFormat::Rgb8, &gains),
+                   Err(ConvertError::TooShort { len: 3, needed: 4 }));
+    }
+}
@@ -0,0 +1,290 @@
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use fourcc::{FourCc, ParseFourCcError};
+use {Constraints, Dir, Fmt, Pref, Res, Speed, Weights};
+
+/// Why a `ConstraintsBuilder` would not build
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    BadFormat(ParseFourCcError),
+    /// A value nothing can satisfy, like a frame rate of zero
+    Invalid(&'static str),
+    /// Two settings that rule each other out, like a minimum above a maximum
+    Contradiction(&'static str, &'static str),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            BuildError::BadFormat(ref err) => Display::fmt(err, fmt),
+            BuildError::Invalid(name) => write!(fmt, "{} can never be satisfied", name),
+            BuildError::Contradiction(a, b) => write!(fmt, "{} and {} rule each other out", a, b),
+        }
+    }
+}
+
+impl StdError for BuildError {}
+
+/// Builds `Constraints` a setting at a time, like
+/// `Constraints::builder().prefer_format("MJPG").min_resolution(640, 480).max_fps(30).build()`
+#[derive(Clone, Default)]
+pub struct ConstraintsBuilder {
+    constraints: Constraints,
+    /// The first format that didn't parse, reported by `build`
+    bad_format: Option<ParseFourCcError>,
+}
+
+impl Constraints {
+    pub fn builder() -> ConstraintsBuilder {
+        ConstraintsBuilder::default()
+    }
+
+    /// Checks what `ConstraintsBuilder::build` checks, for constraints made
+    /// some other way, like those read from a profiles file
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self.nbuffers == 0 {
+            return Err(BuildError::Invalid("nbuffers"));
+        }
+        if let Some(ref fmt) = self.formats {
+            try!(check_fmt(fmt));
+        }
+        if let Some(ref res) = self.resolutions {
+            try!(check_res(res));
+        }
+        if let Some(ref speed) = self.speeds {
+            try!(check_speed(speed));
+        }
+        Ok(())
+    }
+}
+
+impl ConstraintsBuilder {
+    fn fmt(&mut self) -> &mut Fmt {
+        self.constraints.formats.get_or_insert_with(Fmt::default)
+    }
+
+    fn res(&mut self) -> &mut Res {
+        self.constraints.resolutions.get_or_insert_with(Res::default)
+    }
+
+    fn speed(&mut self) -> &mut Speed {
+        self.constraints.speeds.get_or_insert_with(Speed::default)
+    }
+
+    /// Only accept this format, or the others preferred so far.
+    /// Each call adds one, less wanted than the last.
+    pub fn prefer_format(mut self, format: &str) -> Self {
+        match format.parse::<FourCc>() {
+            Ok(format) => self.fmt().priorities.get_or_insert_with(Vec::new).push(format),
+            Err(err) => self.bad_format = self.bad_format.or(Some(err)),
+        }
+        self
+    }
+
+    pub fn emulated(mut self, pref: Pref) -> Self {
+        self.fmt().emulate = pref;
+        self
+    }
+
+    pub fn compressed(mut self, pref: Pref) -> Self {
+        self.fmt().compress = pref;
+        self
+    }
+
+    pub fn resolution_dir(mut self, dir: Dir) -> Self {
+        self.res().dir = dir;
+        self
+    }
+
+    pub fn min_resolution(mut self, width: u32, height: u32) -> Self {
+        self.res().min = Some((width, height));
+        self
+    }
+
+    pub fn max_resolution(mut self, width: u32, height: u32) -> Self {
+        self.res().max = Some((width, height));
+        self
+    }
+
+    pub fn min_pixels(mut self, pixels: u32) -> Self {
+        self.res().min_pixels = Some(pixels);
+        self
+    }
+
+    pub fn max_pixels(mut self, pixels: u32) -> Self {
+        self.res().max_pixels = Some(pixels);
+        self
+    }
+
+    pub fn aspect(mut self, width: u32, height: u32) -> Self {
+        self.res().aspect = Some((width, height));
+        self
+    }
+
+    /// Aim for this resolution, which also sets `Dir::Nearest`
+    pub fn target_resolution(mut self, width: u32, height: u32) -> Self {
+        let res = self.res();
+        res.target = Some((width, height));
+        res.dir = Dir::Nearest;
+        self
+    }
+
+    pub fn fps_dir(mut self, dir: Dir) -> Self {
+        self.speed().dir = dir;
+        self
+    }
+
+    /// Rates take integers as well as floats, like `min_fps(15)` or `min_fps(29.97)`
+    pub fn min_fps<F: Into<f64>>(mut self, fps: F) -> Self {
+        self.speed().min_fps = Some(fps.into() as f32);
+        self
+    }
+
+    pub fn max_fps<F: Into<f64>>(mut self, fps: F) -> Self {
+        self.speed().max_fps = Some(fps.into() as f32);
+        self
+    }
+
+    /// Aim for this frame rate, which also sets `Dir::Nearest`
+    pub fn target_fps<F: Into<f64>>(mut self, fps: F) -> Self {
+        let speed = self.speed();
+        speed.target_fps = Some(fps.into() as f32);
+        speed.dir = Dir::Nearest;
+        self
+    }
+
+    pub fn weights(mut self, weights: Weights) -> Self {
+        self.constraints.weights = weights;
+        self
+    }
+
+    /// One of the `consts::FIELD_*` values
+    pub fn field(mut self, field: u32) -> Self {
+        self.constraints.field = field;
+        self
+    }
+
+    pub fn nbuffers(mut self, nbuffers: u32) -> Self {
+        self.constraints.nbuffers = nbuffers;
+        self
+    }
+
+    /// The constraints, unless some of them can never be met together
+    pub fn build(self) -> Result<Constraints, BuildError> {
+        if let Some(err) = self.bad_format {
+            return Err(BuildError::BadFormat(err));
+        }
+        try!(self.constraints.validate());
+        Ok(self.constraints)
+    }
+}
+
+fn check_fmt(fmt: &Fmt) -> Result<(), BuildError> {
+    let priorities = match fmt.priorities {
+        Some(ref priorities) => priorities,
+        None => return Ok(()),
+    };
+    // Formats we know nothing about could be either
+    let all = |compressed: bool| {
+        priorities.iter().all(|format| format.is_compressed() == Some(compressed))
+    };
+    let contradiction = BuildError::Contradiction("Fmt.compress", "Fmt.priorities");
+    match fmt.compress {
+        Pref::Only if all(false) => Err(contradiction),
+        Pref::Never if all(true) => Err(contradiction),
+        _ => Ok(()),
+    }
+}
+
+fn check_res(res: &Res) -> Result<(), BuildError> {
+    if let (Some(min), Some(max)) = (res.min, res.max) {
+        if min.0 > max.0 || min.1 > max.1 {
+            return Err(BuildError::Contradiction("Res.min", "Res.max"));
+        }
+    }
+    if let (Some(min), Some(max)) = (res.min_pixels, res.max_pixels) {
+        if min > max {
+            return Err(BuildError::Contradiction("Res.min_pixels", "Res.max_pixels"));
+        }
+    }
+    if let (Some(min), Some(max)) = (res.min, res.max_pixels) {
+        if min.0 as u64 * min.1 as u64 > max as u64 {
+            return Err(BuildError::Contradiction("Res.min", "Res.max_pixels"));
+        }
+    }
+    if let (Some(max), Some(min)) = (res.max, res.min_pixels) {
+        if (max.0 as u64 * max.1 as u64) < min as u64 {
+            return Err(BuildError::Contradiction("Res.max", "Res.min_pixels"));
+        }
+    }
+    if let Some((width, height)) = res.aspect {
+        if width == 0 || height == 0 {
+            return Err(BuildError::Invalid("Res.aspect"));
+        }
+    }
+    Ok(())
+}
+
+fn check_speed(speed: &Speed) -> Result<(), BuildError> {
+    let names = [("Speed.min_fps", speed.min_fps), ("Speed.max_fps", speed.max_fps),
+                 ("Speed.target_fps", speed.target_fps)];
+    for &(name, fps) in &names {
+        if let Some(fps) = fps {
+            if fps.is_nan() || fps <= 0. {
+                return Err(BuildError::Invalid(name));
+            }
+        }
+    }
+    if let (Some(min), Some(max)) = (speed.min_fps, speed.max_fps) {
+        if min > max {
+            return Err(BuildError::Contradiction("Speed.min_fps", "Speed.max_fps"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml;
+
+    #[test]
+    fn build_checks() {
+        assert!(Constraints::builder().prefer_format("MJPG").max_fps(30.).build().is_ok());
+        match Constraints::builder().prefer_format("TOOLONG").build() {
+            Err(BuildError::BadFormat(_)) => (),
+            other => panic!("{:?}", other.err()),
+        }
+        let err = Constraints::builder().min_fps(30.).max_fps(15.).build().err();
+        assert_eq!(err, Some(BuildError::Contradiction("Speed.min_fps", "Speed.max_fps")));
+        let err = Constraints::builder().min_fps(0.).build().err();
+        assert_eq!(err, Some(BuildError::Invalid("Speed.min_fps")));
+        // Whole rates need no decimal point
+        let speed = Constraints::builder().min_fps(15).max_fps(30u8).target_fps(25.5f32)
+            .build().unwrap().speeds.unwrap();
+        assert_eq!((speed.min_fps, speed.max_fps, speed.target_fps),
+                   (Some(15.), Some(30.), Some(25.5)));
+        let err = Constraints::builder().max_fps(-1).build().err();
+        assert_eq!(err, Some(BuildError::Invalid("Speed.max_fps")));
+        let err = Constraints::builder().min_resolution(1280, 720).max_resolution(640, 480)
+            .build().err();
+        assert_eq!(err, Some(BuildError::Contradiction("Res.min", "Res.max")));
+        let err = Constraints::builder().prefer_format("YUYV").compressed(Pref::Only)
+            .build().err();
+        assert_eq!(err, Some(BuildError::Contradiction("Fmt.compress", "Fmt.priorities")));
+    }
+
+    #[test]
+    fn validate_read_constraints() {
+        let constraints: Constraints = toml::from_str("[speeds]\nmin_fps = 30.0\nmax_fps = 15.0\n")
+            .unwrap();
+        let err = constraints.validate().err();
+        assert_eq!(err, Some(BuildError::Contradiction("Speed.min_fps", "Speed.max_fps")));
+        let constraints: Constraints = toml::from_str("nbuffers = 0\n").unwrap();
+        assert_eq!(constraints.validate().err(), Some(BuildError::Invalid("nbuffers")));
+        assert!(Constraints::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+
+use rscam::{Config, FormatInfo};
+
+use controls::{ControlInfo, ControlValue};
+use fourcc::FourCc;
+use frame::Frame;
+use source::CaptureSource;
+use DisStepInfo;
+use V4l2Result;
+
+/// A format and one of its resolutions
+type IntervalKey = (FourCc, (u32, u32));
+
+/// Wraps a `CaptureSource` and remembers what it offers, so it can be
+/// searched for any number of `Constraints` while each format, resolution
+/// and interval list is only asked of the device once.
+/// Slow USB cameras take a while to answer each of those.
+pub struct Cached<S> {
+    source: S,
+    formats: RefCell<Option<Vec<FormatInfo>>>,
+    resolutions: RefCell<HashMap<FourCc, DisStepInfo>>,
+    intervals: RefCell<HashMap<IntervalKey, DisStepInfo>>,
+}
+
+/// rscam's `FormatInfo` can't be cloned
+fn copy_info(info: &FormatInfo) -> FormatInfo {
+    FormatInfo {
+        format: info.format,
+        description: info.description.clone(),
+        compressed: info.compressed,
+        emulated: info.emulated,
+    }
+}
+
+impl<S: CaptureSource> Cached<S> {
+    pub fn new(source: S) -> Self {
+        Cached {
+            source: source,
+            formats: RefCell::new(None),
+            resolutions: RefCell::new(HashMap::new()),
+            intervals: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.source
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// The source, ready to be started with one of the configurations found
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: CaptureSource> CaptureSource for Cached<S> {
+    fn formats(&self) -> Vec<FormatInfo> {
+        let mut formats = self.formats.borrow_mut();
+        if formats.is_none() {
+            *formats = Some(self.source.formats());
+        }
+        formats.as_ref().map_or_else(Vec::new, |formats| formats.iter().map(copy_info).collect())
+    }
+
+    /// Failures aren't remembered, the next call asks again
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        if let Some(resolutions) = self.resolutions.borrow().get(&format) {
+            return Ok(resolutions.clone());
+        }
+        let resolutions = try!(self.source.resolutions(format));
+        self.resolutions.borrow_mut().insert(format, resolutions.clone());
+        Ok(resolutions)
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        let key = (format, resolution);
+        if let Some(intervals) = self.intervals.borrow().get(&key) {
+            return Ok(intervals.clone());
+        }
+        let intervals = try!(self.source.intervals(format, resolution));
+        self.intervals.borrow_mut().insert(key, intervals.clone());
+        Ok(intervals)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        self.source.start(config)
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        self.source.capture()
+    }
+
+    // Controls change, so they are always read afresh
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        self.source.controls()
+    }
+
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        self.source.control(id)
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        self.source.write_control(id, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use mock::{caps, MockCamera};
+    use {search, Constraints, Dir};
+
+    /// A mock that counts what it is asked
+    struct Counting {
+        camera: MockCamera,
+        formats: Cell<usize>,
+        resolutions: Cell<usize>,
+        intervals: Cell<usize>,
+    }
+
+    impl Counting {
+        fn asked(&self) -> (usize, usize, usize) {
+            (self.formats.get(), self.resolutions.get(), self.intervals.get())
+        }
+    }
+
+    impl CaptureSource for Counting {
+        fn formats(&self) -> Vec<FormatInfo> {
+            self.formats.set(self.formats.get() + 1);
+            self.camera.formats()
+        }
+
+        fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+            self.resolutions.set(self.resolutions.get() + 1);
+            self.camera.resolutions(format)
+        }
+
+        fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+            self.intervals.set(self.intervals.get() + 1);
+            self.camera.intervals(format, resolution)
+        }
+
+        fn start(&mut self, config: &Config) -> V4l2Result<()> {
+            self.camera.start(config)
+        }
+
+        fn capture(&mut self) -> io::Result<Frame> {
+            self.camera.capture()
+        }
+    }
+
+    #[test]
+    fn searches_ask_the_source_once() {
+        let mut yuyv = caps(FourCc::YUYV, false, &[(640, 480), (320, 240)], &[(1, 30)]);
+        // 320x240 has no intervals, so listing them fails every time
+        yuyv.intervals.truncate(1);
+        let mjpg = caps(FourCc::MJPG, true, &[(1280, 720)], &[(1, 30), (1, 15)]);
+        let camera = Cached::new(Counting {
+            camera: MockCamera::new(vec![yuyv, mjpg], Vec::new()),
+            formats: Cell::new(0),
+            resolutions: Cell::new(0),
+            intervals: Cell::new(0),
+        });
+        let best = search(&camera, Constraints::default()).unwrap();
+        assert_eq!(camera.get_ref().asked(), (1, 2, 3));
+        let fast = Constraints::builder().resolution_dir(Dir::Lowest).fps_dir(Dir::Highest)
+            .build().unwrap();
+        let fastest = search(&camera, fast).unwrap();
+        // Only the failure is asked again
+        assert_eq!(camera.get_ref().asked(), (1, 2, 4));
+        assert_eq!(best[0].resolution, (1280, 720));
+        assert_eq!(fastest[0].resolution, (640, 480));
+    }
+}
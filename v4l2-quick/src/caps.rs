@@ -0,0 +1,109 @@
+use rscam::FormatInfo;
+
+use fourcc::FourCc;
+use source::CaptureSource;
+use DisStepInfo;
+use V4l2Result;
+use expand_sizes;
+
+/// A pixel format and every resolution and interval it supports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FormatCaps {
+    pub format: FourCc,
+    pub description: String,
+    pub compressed: bool,
+    pub emulated: bool,
+    pub resolutions: DisStepInfo,
+    /// The intervals on offer at each resolution
+    pub intervals: Vec<((u32, u32), DisStepInfo)>,
+    /// Resolutions whose intervals could not be listed, and why. Drivers
+    /// without ENUM_FRAMEINTERVALS fail every one of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<((u32, u32), String)>,
+}
+
+impl FormatCaps {
+    /// Lists everything `source` offers. Stepwise resolutions are sampled
+    /// the way the picker samples them, at the common sizes in range.
+    /// Failing to list the intervals of a resolution goes in `failures`.
+    pub fn enumerate<S: CaptureSource + ?Sized>(source: &S) -> V4l2Result<Vec<FormatCaps>> {
+        let mut tree = Vec::new();
+        for info in source.formats() {
+            let format = FourCc(info.format);
+            let resolutions = try!(source.resolutions(format));
+            let sizes = match resolutions {
+                DisStepInfo::Discretes(ref sizes) => sizes.clone(),
+                DisStepInfo::Stepwise{min, max, step} => expand_sizes(min, max, step, &[]),
+                DisStepInfo::StepwiseFraction{..} => Vec::new(),
+            };
+            let mut intervals = Vec::with_capacity(sizes.len());
+            let mut failures = Vec::new();
+            for size in sizes {
+                match source.intervals(format, size) {
+                    Ok(offered) => intervals.push((size, offered)),
+                    Err(err) => failures.push((size, err.to_string())),
+                }
+            }
+            tree.push(FormatCaps {
+                format: format,
+                description: info.description,
+                compressed: info.compressed,
+                emulated: info.emulated,
+                resolutions: resolutions,
+                intervals: intervals,
+                failures: failures,
+            });
+        }
+        Ok(tree)
+    }
+
+    pub fn info(&self) -> FormatInfo {
+        FormatInfo {
+            format: self.format.0,
+            description: self.description.clone(),
+            compressed: self.compressed,
+            emulated: self.emulated,
+        }
+    }
+
+    /// The intervals on offer at `resolution`. Stepwise resolutions can't
+    /// all be listed, so those without an entry borrow the intervals of
+    /// the listed resolution closest in size.
+    pub fn intervals_at(&self, resolution: (u32, u32)) -> Option<&DisStepInfo> {
+        if !self.resolutions.contains(resolution) {
+            return None;
+        }
+        let exact = self.intervals.iter().find(|&&(size, _)| size == resolution);
+        if exact.is_some() {
+            return exact.map(|(_, intervals)| intervals);
+        }
+        if let DisStepInfo::Discretes(_) = self.resolutions {
+            return None;
+        }
+        let pixels = |size: (u32, u32)| size.0 as i64 * size.1 as i64;
+        self.intervals.iter()
+            .min_by_key(|&&(size, _)| (pixels(size) - pixels(resolution)).abs())
+            .map(|(_, intervals)| intervals)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{caps, MockCamera};
+
+    #[test]
+    fn enumerate_records_interval_failures() {
+        let mut yuyv = caps(FourCc::YUYV, false, &[(640, 480), (320, 240)], &[(1, 30)]);
+        // No intervals at 320x240, so asking for them fails
+        yuyv.intervals.truncate(1);
+        let camera = MockCamera::new(vec![yuyv], Vec::new());
+        let tree = FormatCaps::enumerate(&camera).unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].intervals.len(), 1);
+        assert_eq!(tree[0].intervals[0].0, (640, 480));
+        assert_eq!(tree[0].failures.len(), 1);
+        assert_eq!(tree[0].failures[0].0, (320, 240));
+    }
+}
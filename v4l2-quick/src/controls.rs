@@ -0,0 +1,329 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io;
+
+use rscam::{Control, CtrlData};
+use rscam::consts::{FLAG_DISABLED, FLAG_GRABBED, FLAG_INACTIVE, FLAG_READ_ONLY, FLAG_WRITE_ONLY};
+
+use error::Error;
+use source::CaptureSource;
+
+/// What a control takes, and the values it accepts
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlKind {
+    Integer { min: i64, max: i64, step: i64, default: i64 },
+    Boolean { default: bool },
+    /// Named entries, by index. The indices can have gaps.
+    Menu { items: Vec<(u32, String)>, default: u32 },
+    /// Numbered entries, by index, like the link frequencies of a sensor
+    IntegerMenu { items: Vec<(u32, i64)>, default: u32 },
+    Bitmask { max: u32, default: u32 },
+    /// Does something when written, like restoring the defaults
+    Button,
+    /// rscam can read these but not write them
+    Text { min: u32, max: u32 },
+    /// A kind rscam doesn't know, it can't be read or written
+    Other,
+}
+
+/// The value of a control, or one to write to it
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlValue {
+    /// Integers, menu indices and bitmasks
+    Integer(i64),
+    Boolean(bool),
+    Text(String),
+    /// Buttons hold nothing, writing this presses them
+    Press,
+}
+
+impl ControlValue {
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            ControlValue::Integer(value) => Some(value),
+            ControlValue::Boolean(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            ControlValue::Boolean(value) => Some(value),
+            ControlValue::Integer(value) => Some(value != 0),
+            _ => None,
+        }
+    }
+}
+
+/// Why a control would not take a value
+#[derive(Clone, Debug, PartialEq)]
+pub enum BadValue {
+    /// Not the kind of value the control takes, like text for an integer
+    WrongKind,
+    /// Outside the range, or between its steps
+    OutOfRange { min: i64, max: i64, step: i64 },
+    /// Not the index of a menu entry
+    NoSuchItem,
+    /// Read only, or disabled, or inactive while an automatic mode is on
+    NotWritable,
+}
+
+impl Display for BadValue {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            BadValue::WrongKind => fmt.write_str("the control takes another kind of value"),
+            BadValue::OutOfRange{min, max, step} => {
+                write!(fmt, "the control takes {} to {} in steps of {}", min, max, step)
+            },
+            BadValue::NoSuchItem => fmt.write_str("the menu has no such entry"),
+            BadValue::NotWritable => fmt.write_str("the control can't be written right now"),
+        }
+    }
+}
+
+/// A control, like brightness or exposure, and its current value
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlInfo {
+    /// One of the `consts::CID_*` ids
+    pub id: u32,
+    pub name: String,
+    pub kind: ControlKind,
+    /// Missing for buttons, and for controls that can only be written
+    pub value: Option<ControlValue>,
+    /// The `consts::FLAG_*` flags
+    pub flags: u32,
+}
+
+impl ControlInfo {
+    /// Whether it can be written at the moment. Inactive controls, like
+    /// exposure while auto exposure is on, would take a write and ignore it,
+    /// so they don't count.
+    pub fn is_writable(&self) -> bool {
+        let flags = FLAG_DISABLED | FLAG_GRABBED | FLAG_READ_ONLY | FLAG_INACTIVE;
+        match self.kind {
+            ControlKind::Text{..} | ControlKind::Other => false,
+            _ => self.flags & flags == 0,
+        }
+    }
+
+    /// The index of the menu entry called `name`, ignoring case
+    pub fn item(&self, name: &str) -> Option<u32> {
+        match self.kind {
+            ControlKind::Menu{ref items, ..} => items.iter()
+                .find(|(_, item)| item.eq_ignore_ascii_case(name))
+                .map(|&(index, _)| index),
+            _ => None,
+        }
+    }
+
+    /// Checks `value` against the kind, range and flags of the control
+    pub fn check(&self, value: &ControlValue) -> Result<(), BadValue> {
+        if !self.is_writable() {
+            return Err(BadValue::NotWritable);
+        }
+        match (&self.kind, value) {
+            (&ControlKind::Integer{min, max, step, ..}, &ControlValue::Integer(value)) => {
+                if value < min || value > max || (step > 1 && (value - min) % step != 0) {
+                    return Err(BadValue::OutOfRange { min: min, max: max, step: step });
+                }
+                Ok(())
+            },
+            (&ControlKind::Boolean{..}, &ControlValue::Boolean(_)) => Ok(()),
+            (&ControlKind::Boolean{..}, &ControlValue::Integer(0)) => Ok(()),
+            (&ControlKind::Boolean{..}, &ControlValue::Integer(1)) => Ok(()),
+            // Menus take the index of one of their entries
+            (ControlKind::Menu{items, ..}, &ControlValue::Integer(index)) => {
+                match items.iter().find(|&&(item, _)| item as i64 == index) {
+                    Some(_) => Ok(()),
+                    None => Err(BadValue::NoSuchItem),
+                }
+            },
+            (ControlKind::IntegerMenu{items, ..}, &ControlValue::Integer(index)) => {
+                match items.iter().find(|&&(item, _)| item as i64 == index) {
+                    Some(_) => Ok(()),
+                    None => Err(BadValue::NoSuchItem),
+                }
+            },
+            (&ControlKind::Bitmask{max, ..}, &ControlValue::Integer(value)) => {
+                if value < 0 || value as u64 & !(max as u64) != 0 {
+                    return Err(BadValue::OutOfRange { min: 0, max: max as i64, step: 1 });
+                }
+                Ok(())
+            },
+            (&ControlKind::Button, &ControlValue::Press) => Ok(()),
+            _ => Err(BadValue::WrongKind),
+        }
+    }
+}
+
+impl From<Control> for ControlInfo {
+    fn from(control: Control) -> Self {
+        let write_only = control.flags & FLAG_WRITE_ONLY != 0;
+        let (kind, value) = match control.data {
+            CtrlData::Integer{value, default, minimum, maximum, step} => (
+                ControlKind::Integer {
+                    min: minimum as i64,
+                    max: maximum as i64,
+                    step: step as i64,
+                    default: default as i64,
+                },
+                Some(ControlValue::Integer(value as i64)),
+            ),
+            CtrlData::Integer64{value, default, minimum, maximum, step} => (
+                ControlKind::Integer { min: minimum, max: maximum, step: step, default: default },
+                Some(ControlValue::Integer(value)),
+            ),
+            CtrlData::Boolean{value, default} => {
+                (ControlKind::Boolean { default: default }, Some(ControlValue::Boolean(value)))
+            },
+            CtrlData::Menu{value, default, items} => (
+                ControlKind::Menu {
+                    items: items.into_iter().map(|item| (item.index, item.name)).collect(),
+                    default: default,
+                },
+                Some(ControlValue::Integer(value as i64)),
+            ),
+            CtrlData::IntegerMenu{value, default, items} => (
+                ControlKind::IntegerMenu {
+                    items: items.into_iter().map(|item| (item.index, item.value)).collect(),
+                    default: default,
+                },
+                Some(ControlValue::Integer(value as i64)),
+            ),
+            CtrlData::Bitmask{value, default, maximum} => (
+                ControlKind::Bitmask { max: maximum, default: default },
+                Some(ControlValue::Integer(value as i64)),
+            ),
+            CtrlData::String{value, minimum, maximum, ..} => {
+                (ControlKind::Text { min: minimum, max: maximum }, Some(ControlValue::Text(value)))
+            },
+            CtrlData::Button => (ControlKind::Button, None),
+            CtrlData::CtrlClass | CtrlData::Unknown => (ControlKind::Other, None),
+        };
+        ControlInfo {
+            id: control.id,
+            name: control.name,
+            kind: kind,
+            value: if write_only { None } else { value },
+            flags: control.flags,
+        }
+    }
+}
+
+/// The control of `source` called `name`, ignoring case
+pub fn find_control<S: CaptureSource + ?Sized>(source: &S, name: &str) -> io::Result<ControlInfo> {
+    let controls = try!(source.controls());
+    controls.into_iter()
+        .find(|control| control.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no control called {}", name))
+        })
+}
+
+/// Sets control `id` of `source` to `value`, after checking the control
+/// would take it, so a typo doesn't end up clamped by the driver
+pub fn set_control<S: CaptureSource + ?Sized>(source: &mut S, id: u32, value: ControlValue)
+        -> Result<(), Error> {
+    let control = try!(source.control(id));
+    if let Err(problem) = control.check(&value) {
+        return Err(Error::Control(control.name, problem));
+    }
+    try!(source.write_control(id, &value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{control, webcam_controls, MockCamera};
+    use rscam::consts::{CID_BRIGHTNESS, CID_EXPOSURE_ABSOLUTE, CID_POWER_LINE_FREQUENCY};
+
+    fn find(id: u32) -> ControlInfo {
+        webcam_controls().into_iter().find(|control| control.id == id).unwrap()
+    }
+
+    #[test]
+    fn integers_keep_to_range_and_step() {
+        let brightness = find(CID_BRIGHTNESS);
+        let out = BadValue::OutOfRange { min: -64, max: 64, step: 4 };
+        assert_eq!(brightness.check(&ControlValue::Integer(-64)), Ok(()));
+        assert_eq!(brightness.check(&ControlValue::Integer(8)), Ok(()));
+        assert_eq!(brightness.check(&ControlValue::Integer(6)), Err(out.clone()));
+        assert_eq!(brightness.check(&ControlValue::Integer(68)), Err(out.clone()));
+        assert_eq!(brightness.check(&ControlValue::Integer(-68)), Err(out));
+        assert_eq!(brightness.check(&ControlValue::Boolean(true)), Err(BadValue::WrongKind));
+    }
+
+    #[test]
+    fn menus_take_only_their_indices() {
+        let power = find(CID_POWER_LINE_FREQUENCY);
+        assert_eq!(power.check(&ControlValue::Integer(0)), Ok(()));
+        assert_eq!(power.check(&ControlValue::Integer(2)), Ok(()));
+        // The gap the driver left for 50 Hz
+        assert_eq!(power.check(&ControlValue::Integer(1)), Err(BadValue::NoSuchItem));
+        assert_eq!(power.check(&ControlValue::Integer(3)), Err(BadValue::NoSuchItem));
+        assert_eq!((power.item("60 hz"), power.item("50 Hz")), (Some(2), None));
+        let frequencies = control(1, "Link Frequency", ControlKind::IntegerMenu {
+            items: vec![(0, 400000000), (2, 800000000)],
+            default: 0,
+        }, Some(ControlValue::Integer(0)));
+        assert_eq!(frequencies.check(&ControlValue::Integer(2)), Ok(()));
+        assert_eq!(frequencies.check(&ControlValue::Integer(1)), Err(BadValue::NoSuchItem));
+        assert_eq!(frequencies.item("0"), None);
+    }
+
+    #[test]
+    fn bitmasks_keep_to_their_bits() {
+        let faults = control(1, "Flash Faults", ControlKind::Bitmask { max: 0b101, default: 0 },
+                             Some(ControlValue::Integer(0)));
+        let out = BadValue::OutOfRange { min: 0, max: 0b101, step: 1 };
+        assert_eq!(faults.check(&ControlValue::Integer(0)), Ok(()));
+        assert_eq!(faults.check(&ControlValue::Integer(0b101)), Ok(()));
+        assert_eq!(faults.check(&ControlValue::Integer(0b010)), Err(out.clone()));
+        assert_eq!(faults.check(&ControlValue::Integer(-1)), Err(out));
+    }
+
+    #[test]
+    fn only_writable_controls_take_values() {
+        // Inactive while auto exposure is on
+        let exposure = find(CID_EXPOSURE_ABSOLUTE);
+        assert!(!exposure.is_writable());
+        assert_eq!(exposure.check(&ControlValue::Integer(100)), Err(BadValue::NotWritable));
+        let mut brightness = find(CID_BRIGHTNESS);
+        brightness.flags = FLAG_READ_ONLY;
+        assert_eq!(brightness.check(&ControlValue::Integer(0)), Err(BadValue::NotWritable));
+        let name = control(1, "Name", ControlKind::Text { min: 0, max: 32 }, None);
+        assert_eq!(name.check(&ControlValue::Text("a".to_string())), Err(BadValue::NotWritable));
+        let reset = control(2, "Reset", ControlKind::Button, None);
+        assert_eq!(reset.check(&ControlValue::Press), Ok(()));
+        assert_eq!(reset.check(&ControlValue::Integer(1)), Err(BadValue::WrongKind));
+        let auto = control(3, "White Balance, Auto", ControlKind::Boolean { default: true }, None);
+        assert_eq!(auto.check(&ControlValue::Integer(1)), Ok(()));
+        assert_eq!(auto.check(&ControlValue::Integer(2)), Err(BadValue::WrongKind));
+    }
+
+    #[test]
+    fn set_control_checks_before_writing() {
+        let mut camera = MockCamera::default();
+        camera.controls = webcam_controls();
+        set_control(&mut camera, CID_BRIGHTNESS, ControlValue::Integer(8)).unwrap();
+        assert_eq!(camera.control(CID_BRIGHTNESS).unwrap().value, Some(ControlValue::Integer(8)));
+        match set_control(&mut camera, CID_BRIGHTNESS, ControlValue::Integer(7)) {
+            Err(Error::Control(ref name, BadValue::OutOfRange{..})) => {
+                assert_eq!(name, "Brightness")
+            },
+            other => panic!("expected a range error, got {:?}", other),
+        }
+        let value = ControlValue::Integer(100);
+        match set_control(&mut camera, CID_EXPOSURE_ABSOLUTE, value) {
+            Err(Error::Control(_, BadValue::NotWritable)) => (),
+            other => panic!("expected the exposure to be locked, got {:?}", other),
+        }
+        // The rejected writes never reached the camera
+        assert_eq!(camera.control(CID_BRIGHTNESS).unwrap().value, Some(ControlValue::Integer(8)));
+        assert!(set_control(&mut camera, 1, ControlValue::Integer(0)).is_err());
+        assert_eq!(find_control(&camera, "brightness").unwrap().id, CID_BRIGHTNESS);
+        assert!(find_control(&camera, "Contrast").is_err());
+    }
+}
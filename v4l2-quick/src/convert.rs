@@ -0,0 +1,258 @@
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use bayer::{debayer, is_bayer, Gains};
+use fourcc::FourCc;
+
+/// How the pixels of a raw frame are laid out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelLayout {
+    pub format: FourCc,
+    pub resolution: (u32, u32),
+    /// Bytes from the start of one row to the next, in the first plane of
+    /// planar formats. Drivers can pad rows out, `None` means they aren't.
+    pub stride: Option<usize>,
+}
+
+impl PixelLayout {
+    /// A layout without padding
+    pub fn packed(format: FourCc, resolution: (u32, u32)) -> Self {
+        PixelLayout {
+            format: format,
+            resolution: resolution,
+            stride: None,
+        }
+    }
+}
+
+/// What to convert to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RgbFormat {
+    /// Three bytes a pixel
+    Rgb8,
+    /// Four bytes a pixel, the alpha always opaque
+    Rgba8,
+}
+
+impl RgbFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            RgbFormat::Rgb8 => 3,
+            RgbFormat::Rgba8 => 4,
+        }
+    }
+}
+
+/// Why a frame could not be converted
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConvertError {
+    /// Not one of the formats `to_rgb` reads
+    Unsupported(FourCc),
+    /// Rows can't be shorter than their pixels
+    BadStride { stride: usize, min: usize },
+    /// The frame has fewer bytes than the layout needs
+    TooShort { len: usize, needed: usize },
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            ConvertError::Unsupported(format) => {
+                write!(fmt, "{} can't be converted to RGB", format)
+            },
+            ConvertError::BadStride{stride, min} => {
+                write!(fmt, "a stride of {} is less than the {} bytes of a row", stride, min)
+            },
+            ConvertError::TooShort{len, needed} => {
+                write!(fmt, "the frame is {} bytes, the layout needs {}", len, needed)
+            },
+        }
+    }
+}
+
+impl StdError for ConvertError {}
+
+/// The bytes of a row of `width` pixels, in the first plane
+fn row_bytes(format: FourCc, width: usize) -> Option<usize> {
+    match format {
+        // Pixels come in pairs that share their chroma
+        FourCc::YUYV | FourCc::UYVY => Some(width.div_ceil(2) * 4),
+        FourCc::NV12 | FourCc::YU12 | FourCc::GREY => Some(width),
+        FourCc::Y16 => Some(width * 2),
+        FourCc::RGB3 | FourCc::BGR3 => Some(width * 3),
+        _ => None,
+    }
+}
+
+/// Whether `to_rgb` reads `format`
+pub fn is_convertible(format: FourCc) -> bool {
+    row_bytes(format, 0).is_some() || is_bayer(format)
+}
+
+/// BT.601 studio swing YCbCr to RGB, in fixed point
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let (d, e) = (u as i32 - 128, v as i32 - 128);
+    let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+    [clamp(c + 409 * e), clamp(c - 100 * d - 208 * e), clamp(c + 516 * d)]
+}
+
+/// Converts a raw frame laid out as `layout` to packed RGB or RGBA, rows
+/// without padding. Handles YUYV, UYVY, NV12, YU12, RGB3, BGR3, GREY,
+/// Y16, which keeps its top eight bits, and the 8-bit Bayer formats, which
+/// are demosaiced without white balance; `debayer` takes gains.
+pub fn to_rgb(data: &[u8], layout: &PixelLayout, out: RgbFormat) -> Result<Vec<u8>, ConvertError> {
+    let format = layout.format;
+    if is_bayer(format) {
+        return debayer(data, layout, out, &Gains::default());
+    }
+    let (width, height) = (layout.resolution.0 as usize, layout.resolution.1 as usize);
+    let row = try!(row_bytes(format, width).ok_or(ConvertError::Unsupported(format)));
+    let stride = layout.stride.unwrap_or(row);
+    if stride < row {
+        return Err(ConvertError::BadStride { stride: stride, min: row });
+    }
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+    // The chroma planes of 4:2:0 formats, rounding up on odd sizes
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let chroma_at = stride * height;
+    // NV12 chroma rows are as long as the luma ones, but an odd width
+    // leaves them a byte short of the pair at the end
+    let chroma_stride = match format {
+        FourCc::NV12 => stride.max(chroma_width * 2),
+        _ => stride.div_ceil(2),
+    };
+    // The last row of a plane needn't be padded out
+    let needed = match format {
+        FourCc::NV12 => chroma_at + chroma_stride * (chroma_height - 1) + chroma_width * 2,
+        FourCc::YU12 => chroma_at + chroma_stride * (2 * chroma_height - 1) + chroma_width,
+        _ => stride * (height - 1) + row,
+    };
+    if data.len() < needed {
+        return Err(ConvertError::TooShort { len: data.len(), needed: needed });
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * out.bytes_per_pixel());
+    let mut put = |pixel: [u8; 3]| {
+        rgb.extend_from_slice(&pixel);
+        if out == RgbFormat::Rgba8 {
+            rgb.push(0xFF);
+        }
+    };
+    for y in 0..height {
+        let line = &data[y * stride..y * stride + row];
+        // Where this row's chroma starts, for the planar formats
+        let chroma = chroma_at + y / 2 * chroma_stride;
+        for x in 0..width {
+            let pixel = match format {
+                FourCc::YUYV => {
+                    let pair = &line[x / 2 * 4..x / 2 * 4 + 4];
+                    yuv_to_rgb(pair[x % 2 * 2], pair[1], pair[3])
+                },
+                FourCc::UYVY => {
+                    let pair = &line[x / 2 * 4..x / 2 * 4 + 4];
+                    yuv_to_rgb(pair[x % 2 * 2 + 1], pair[0], pair[2])
+                },
+                FourCc::NV12 => {
+                    let pair = chroma + x / 2 * 2;
+                    yuv_to_rgb(line[x], data[pair], data[pair + 1])
+                },
+                FourCc::YU12 => {
+                    // Cr is a whole Cb plane further on
+                    let cb = chroma + x / 2;
+                    yuv_to_rgb(line[x], data[cb], data[cb + chroma_stride * chroma_height])
+                },
+                FourCc::RGB3 => [line[x * 3], line[x * 3 + 1], line[x * 3 + 2]],
+                FourCc::BGR3 => [line[x * 3 + 2], line[x * 3 + 1], line[x * 3]],
+                FourCc::GREY => [line[x]; 3],
+                // Little endian, the high byte is the second
+                FourCc::Y16 => [line[x * 2 + 1]; 3],
+                _ => unreachable!(),
+            };
+            put(pixel);
+        }
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(format: FourCc, resolution: (u32, u32), data: &[u8]) -> Vec<u8> {
+        to_rgb(data, &PixelLayout::packed(format, resolution), RgbFormat::Rgb8).unwrap()
+    }
+
+    #[test]
+    fn yuv_limits() {
+        assert_eq!(yuv_to_rgb(16, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(235, 128, 128), [255, 255, 255]);
+        // Out of range chroma clamps rather than wrapping
+        assert_eq!(yuv_to_rgb(235, 255, 255)[0], 255);
+        assert_eq!(yuv_to_rgb(16, 0, 0)[2], 0);
+    }
+
+    #[test]
+    fn packed_yuv() {
+        let black_white = [0, 0, 0, 255, 255, 255];
+        assert_eq!(convert(FourCc::YUYV, (2, 1), &[16, 128, 235, 128]), black_white);
+        assert_eq!(convert(FourCc::UYVY, (2, 1), &[128, 16, 128, 235]), black_white);
+        // An odd width still takes up the whole pair
+        assert_eq!(convert(FourCc::YUYV, (1, 1), &[235, 128, 16, 128]), [255, 255, 255]);
+    }
+
+    #[test]
+    fn planar_yuv() {
+        let luma = [16, 100, 150, 235];
+        let expected: Vec<u8> = luma.iter().flat_map(|&y| yuv_to_rgb(y, 90, 240).to_vec())
+            .collect();
+        let nv12 = [16, 100, 150, 235, 90, 240];
+        assert_eq!(convert(FourCc::NV12, (2, 2), &nv12), expected);
+        let yu12 = [16, 100, 150, 235, 90, 240];
+        assert_eq!(convert(FourCc::YU12, (2, 2), &yu12), expected);
+        // The last NV12 pixel of an odd width has a pair of its own
+        let nv12 = [16, 16, 16, 128, 128, 90, 240];
+        let rgb = convert(FourCc::NV12, (3, 1), &nv12);
+        assert_eq!(rgb[..6], [0; 6]);
+        assert_eq!(rgb[6..], yuv_to_rgb(16, 90, 240));
+    }
+
+    #[test]
+    fn rgb_and_grey() {
+        assert_eq!(convert(FourCc::RGB3, (1, 1), &[1, 2, 3]), [1, 2, 3]);
+        assert_eq!(convert(FourCc::BGR3, (1, 1), &[1, 2, 3]), [3, 2, 1]);
+        assert_eq!(convert(FourCc::GREY, (2, 1), &[7, 9]), [7, 7, 7, 9, 9, 9]);
+        assert_eq!(convert(FourCc::Y16, (1, 1), &[0xCD, 0xAB]), [0xAB; 3]);
+        let rgba = to_rgb(&[7], &PixelLayout::packed(FourCc::GREY, (1, 1)), RgbFormat::Rgba8);
+        assert_eq!(rgba, Ok(vec![7, 7, 7, 0xFF]));
+    }
+
+    #[test]
+    fn skips_padding() {
+        // Two bytes of padding after the first row, none after the last
+        let layout = PixelLayout { stride: Some(4), ..PixelLayout::packed(FourCc::GREY, (2, 2)) };
+        let rgb = to_rgb(&[1, 2, 0xEE, 0xEE, 3, 4], &layout, RgbFormat::Rgb8).unwrap();
+        assert_eq!(rgb, [1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+        // YU12 chroma rows are padded by half as much
+        let layout = PixelLayout { stride: Some(4), ..PixelLayout::packed(FourCc::YU12, (2, 2)) };
+        let yu12 = [16, 16, 0, 0, 16, 16, 0, 0, 90, 0, 240];
+        let rgb = to_rgb(&yu12, &layout, RgbFormat::Rgb8).unwrap();
+        assert_eq!(rgb[..3], yuv_to_rgb(16, 90, 240));
+    }
+
+    #[test]
+    fn errors() {
+        let rgb = |data: &[u8], layout: PixelLayout| to_rgb(data, &layout, RgbFormat::Rgb8);
+        assert_eq!(rgb(&[0; 4], PixelLayout::packed(FourCc::MJPG, (1, 1))),
+                   Err(ConvertError::Unsupported(FourCc::MJPG)));
+        let layout = PixelLayout { stride: Some(2), ..PixelLayout::packed(FourCc::RGB3, (1, 1)) };
+        assert_eq!(rgb(&[0; 4], layout), Err(ConvertError::BadStride { stride: 2, min: 3 }));
+        assert_eq!(rgb(&[0; 5], PixelLayout::packed(FourCc::NV12, (2, 2))),
+                   Err(ConvertError::TooShort { len: 5, needed: 6 }));
+        assert_eq!(rgb(&[], PixelLayout::packed(FourCc::GREY, (0, 4))), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rscam::Camera;
+use rscam::Error as V4l2Error;
+
+use caps::FormatCaps;
+use ffi;
+use V4l2Result;
+
+/// A V4L2 device node and what it can do
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub path: PathBuf,
+    /// Links to it under /dev/v4l/by-id, which survive replugging
+    pub ids: Vec<PathBuf>,
+    pub driver: String,
+    /// The name of the camera, like "HD Pro Webcam C920"
+    pub card: String,
+    pub bus_info: String,
+    /// The kernel version the driver came with, as (major, minor, patch)
+    pub version: (u32, u32, u32),
+    /// The `Device::*` capability flags of this node
+    pub capabilities: u32,
+    /// Every format it captures in, empty unless it is a capture node
+    pub formats: Vec<FormatCaps>,
+}
+
+impl Device {
+    pub const VIDEO_CAPTURE: u32 = 0x00000001;
+    pub const VIDEO_OUTPUT: u32 = 0x00000002;
+    pub const VIDEO_OVERLAY: u32 = 0x00000004;
+    pub const VIDEO_CAPTURE_MPLANE: u32 = 0x00001000;
+    pub const VIDEO_M2M: u32 = 0x00008000;
+    pub const META_CAPTURE: u32 = 0x00800000;
+    pub const READWRITE: u32 = 0x01000000;
+    pub const STREAMING: u32 = 0x04000000;
+    /// Set in the device-wide flags if there are per-node ones
+    pub const DEVICE_CAPS: u32 = 0x80000000;
+
+    /// Whether it captures single-planar video, the only kind rscam streams
+    pub fn is_capture(&self) -> bool {
+        self.capabilities & Device::VIDEO_CAPTURE != 0
+    }
+
+    /// Whether frames can be streamed through mapped buffers
+    pub fn is_streaming(&self) -> bool {
+        self.capabilities & Device::STREAMING != 0
+    }
+}
+
+/// Asks the device at `path` what it is and enumerates its formats
+pub fn query<P: AsRef<Path>>(path: P) -> V4l2Result<Device> {
+    let path = path.as_ref();
+    let cap = {
+        let file = try!(OpenOptions::new().read(true).write(true).open(path));
+        try!(ffi::query_cap(&file))
+    };
+    // Drivers with several nodes describe each one in device_caps
+    let capabilities = if cap.capabilities & Device::DEVICE_CAPS != 0 {
+        cap.device_caps
+    } else {
+        cap.capabilities
+    };
+    let mut device = Device {
+        path: path.to_path_buf(),
+        ids: by_id(path),
+        driver: ffi::c_string(&cap.driver),
+        card: ffi::c_string(&cap.card),
+        bus_info: ffi::c_string(&cap.bus_info),
+        version: (cap.version >> 16, (cap.version >> 8) & 0xFF, cap.version & 0xFF),
+        capabilities: capabilities,
+        formats: Vec::new(),
+    };
+    // Metadata and output nodes list formats that can't be captured
+    if device.is_capture() {
+        let name = try!(path.to_str().ok_or_else(|| {
+            V4l2Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "path is not UTF-8"))
+        }));
+        let camera = try!(Camera::new(name));
+        device.formats = try!(FormatCaps::enumerate(&camera));
+    }
+    Ok(device)
+}
+
+/// Every V4L2 device under /dev, in node order. Nodes that can't be opened
+/// or queried, for lack of permission say, are left out; `query` says why.
+pub fn devices() -> io::Result<Vec<Device>> {
+    let mut nodes = Vec::new();
+    for entry in try!(fs::read_dir("/dev")) {
+        let entry = try!(entry);
+        let number = entry.file_name().to_str()
+            .and_then(|name| name.strip_prefix("video"))
+            .and_then(|number| number.parse::<u32>().ok());
+        if let Some(number) = number {
+            nodes.push((number, entry.path()));
+        }
+    }
+    nodes.sort();
+    Ok(nodes.into_iter().filter_map(|(_, path)| query(path).ok()).collect())
+}
+
+/// The links under /dev/v4l/by-id pointing at `path`
+fn by_id(path: &Path) -> Vec<PathBuf> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(_) => return Vec::new(),
+    };
+    let entries = match fs::read_dir("/dev/v4l/by-id") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut ids: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|link| fs::canonicalize(link).ok().as_ref() == Some(&target))
+        .collect();
+    ids.sort();
+    ids
+}
@@ -0,0 +1,264 @@
+use std::io;
+
+use jpeg_encoder::SamplingFactor;
+use rscam::{Config, FormatInfo};
+use rscam::Error as V4l2Error;
+
+use bayer::{debayer, is_bayer, Gains};
+use controls::{ControlInfo, ControlValue};
+use convert::{is_convertible, to_rgb, PixelLayout, RgbFormat};
+use fourcc::FourCc;
+use frame::Frame;
+use jpeg;
+use normalize::{sample_bits, Normalize};
+use source::{Buffer, CaptureSource};
+use DisStepInfo;
+use V4l2Result;
+
+/// Wraps a `CaptureSource` so every frame it captures is a JPEG, for
+/// clients that only show JPEGs. MJPG and JPEG frames pass through as they
+/// are, anything `to_rgb` reads and 16-bit greyscale is compressed.
+/// Starting it in any other format fails, so the next configuration gets a go.
+pub struct ToJpeg<S> {
+    source: S,
+    /// JPEG quality of the compressed frames, from 1 to 100
+    pub quality: u8,
+    /// White balance for Bayer frames
+    pub gains: Gains,
+    /// How 16-bit greyscale frames, like those of thermal cameras, are shown
+    pub normalize: Normalize,
+    /// Pass 16-bit greyscale frames through as they are instead, for
+    /// clients that read the samples themselves
+    pub raw16: bool,
+    /// Bytes from the start of one row to the next in the frames the source
+    /// captures, like the stride `negotiate` reports. `None` means rows
+    /// aren't padded.
+    pub stride: Option<usize>,
+    /// Studio swing, 16 to 235 for luma and 16 to 240 for chroma,
+    /// stretched to the full range JPEG expects
+    luma: [u8; 256],
+    chroma: [u8; 256],
+}
+
+fn stretch(low: f32, high: f32) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let full = (value as f32 - low) * 255. / (high - low);
+        *entry = full.round().clamp(0., 255.) as u8;
+    }
+    table
+}
+
+impl<S: CaptureSource> ToJpeg<S> {
+    pub fn new(source: S) -> Self {
+        ToJpeg {
+            source: source,
+            quality: 85,
+            gains: Gains::default(),
+            normalize: Normalize::default(),
+            raw16: false,
+            stride: None,
+            luma: stretch(16., 235.),
+            chroma: stretch(16., 240.),
+        }
+    }
+
+    /// Whether frames in `format` can be had as JPEGs
+    pub fn supports(format: FourCc) -> bool {
+        format == FourCc::MJPG || format == FourCc::JPEG || is_convertible(format)
+            || sample_bits(format).is_some()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.source
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    fn yuyv(&self, data: &[u8], (width, height): (u32, u32)) -> io::Result<Vec<u8>> {
+        let (width, height) = (width as usize, height as usize);
+        let row = width * 2;
+        let stride = self.stride.unwrap_or(row).max(row);
+        if width % 2 != 0 || height > 0 && data.len() < stride * (height - 1) + row {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "YUYV frame is cut short"));
+        }
+        let mut ycbcr = Vec::with_capacity(width * height * 3);
+        for line in 0..height {
+            for group in data[line * stride..line * stride + row].chunks(4) {
+                let (u, v) = (self.chroma[group[1] as usize], self.chroma[group[3] as usize]);
+                ycbcr.extend_from_slice(&[self.luma[group[0] as usize], u, v]);
+                ycbcr.extend_from_slice(&[self.luma[group[2] as usize], u, v]);
+            }
+        }
+        let resolution = (width as u32, height as u32);
+        jpeg::encode_ycbcr(&ycbcr, resolution, self.quality, SamplingFactor::R_4_2_2)
+    }
+
+    fn nv12(&self, data: &[u8], (width, height): (u32, u32)) -> io::Result<Vec<u8>> {
+        let (width, height) = (width as usize, height as usize);
+        let stride = self.stride.unwrap_or(width).max(width);
+        // A Cb and Cr pair for every 2x2 block, rounding up on odd sizes,
+        // in rows as long as the luma ones
+        let pairs = width.div_ceil(2) * 2;
+        let chroma_stride = stride.max(pairs);
+        let chroma_at = stride * height;
+        if data.len() < chroma_at + height.div_ceil(2) * chroma_stride {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "NV12 frame is cut short"));
+        }
+        let mut ycbcr = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            let luma = &data[row * stride..row * stride + width];
+            let chroma = &data[chroma_at + row / 2 * chroma_stride..];
+            for (column, &y) in luma.iter().enumerate() {
+                let pair = column / 2 * 2;
+                ycbcr.extend_from_slice(&[
+                    self.luma[y as usize],
+                    self.chroma[chroma[pair] as usize],
+                    self.chroma[chroma[pair + 1] as usize],
+                ]);
+            }
+        }
+        let resolution = (width as u32, height as u32);
+        jpeg::encode_ycbcr(&ycbcr, resolution, self.quality, SamplingFactor::R_4_2_0)
+    }
+
+    /// Goes through RGB, for the formats JPEG has no closer match for
+    fn rgb(&self, data: &[u8], layout: &PixelLayout) -> io::Result<Vec<u8>> {
+        let rgb = if sample_bits(layout.format).is_some() {
+            self.normalize.to_rgb(data, layout, RgbFormat::Rgb8)
+        } else if is_bayer(layout.format) {
+            debayer(data, layout, RgbFormat::Rgb8, &self.gains)
+        } else {
+            to_rgb(data, layout, RgbFormat::Rgb8)
+        };
+        let rgb = try!(rgb.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)));
+        jpeg::encode_rgb(&rgb, layout.resolution, self.quality)
+    }
+}
+
+impl<S: CaptureSource> CaptureSource for ToJpeg<S> {
+    fn formats(&self) -> Vec<FormatInfo> {
+        self.source.formats()
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        self.source.resolutions(format)
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        self.source.intervals(format, resolution)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        if config.format.len() != 4 {
+            return Err(V4l2Error::BadFormat);
+        }
+        let format = [config.format[0], config.format[1], config.format[2], config.format[3]];
+        let format = FourCc(format);
+        if !ToJpeg::<S>::supports(format) {
+            return Err(V4l2Error::BadFormat);
+        }
+        self.source.start(config)
+    }
+
+    /// The frame as a JPEG, labelled as one, keeping its timestamp and
+    /// sequence. MJPG and raw 16-bit frames keep their format.
+    fn capture(&mut self) -> io::Result<Frame> {
+        let mut frame = try!(self.source.capture());
+        let resolution = frame.resolution;
+        let data = match frame.format {
+            FourCc::YUYV => try!(self.yuyv(&frame, resolution)),
+            FourCc::NV12 => try!(self.nv12(&frame, resolution)),
+            FourCc::MJPG | FourCc::JPEG => return Ok(frame),
+            format if self.raw16 && sample_bits(format).is_some() => return Ok(frame),
+            format => {
+                let layout = PixelLayout {
+                    format: format,
+                    resolution: resolution,
+                    stride: self.stride,
+                };
+                try!(self.rgb(&frame, &layout))
+            },
+        };
+        frame.data = Buffer::Owned(data);
+        frame.format = FourCc::JPEG;
+        Ok(frame)
+    }
+
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        self.source.controls()
+    }
+
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        self.source.control(id)
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        self.source.write_control(id, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{caps, summary, MockCamera};
+    use {start, ConfigSummary};
+
+    /// Encodes one `format` frame of `data` from a mock, with rows `stride` apart
+    fn encode(format: FourCc, resolution: (u32, u32), data: Vec<u8>, stride: Option<usize>)
+            -> Vec<u8> {
+        let camera = MockCamera::new(vec![caps(format, false, &[resolution], &[(1, 30)])],
+                                     vec![data]);
+        let mut jpeg = ToJpeg::new(camera);
+        jpeg.stride = stride;
+        start(&mut jpeg, &ConfigSummary {
+            stride: stride.map(|stride| stride as u32),
+            .. summary(format, resolution, (1, 30))
+        }).unwrap();
+        jpeg.capture().unwrap().to_vec()
+    }
+
+    /// `packed` with `padding` bytes of junk after every `row` bytes
+    fn pad(packed: &[u8], row: usize, padding: usize) -> Vec<u8> {
+        let mut padded = Vec::new();
+        for line in packed.chunks(row) {
+            padded.extend_from_slice(line);
+            padded.extend(::std::iter::repeat_n(0xAB, padding));
+        }
+        padded
+    }
+
+    fn gradient(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 37 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn padded_rows_encode_like_packed_ones() {
+        let (width, height) = (16, 8);
+        let yuyv = gradient(width * height * 2);
+        assert_eq!(encode(FourCc::YUYV, (16, 8), pad(&yuyv, width * 2, 8), Some(width * 2 + 8)),
+                   encode(FourCc::YUYV, (16, 8), yuyv, None));
+        // Both NV12 planes are padded alike
+        let nv12 = gradient(width * height * 3 / 2);
+        assert_eq!(encode(FourCc::NV12, (16, 8), pad(&nv12, width, 4), Some(width + 4)),
+                   encode(FourCc::NV12, (16, 8), nv12, None));
+        let grey = gradient(width * height);
+        assert_eq!(encode(FourCc::GREY, (16, 8), pad(&grey, width, 16), Some(width + 16)),
+                   encode(FourCc::GREY, (16, 8), grey, None));
+    }
+
+    #[test]
+    fn short_frames_fail() {
+        let camera = MockCamera::new(Vec::new(), Vec::new());
+        let jpeg = ToJpeg::new(camera);
+        assert!(jpeg.yuyv(&[0; 10], (4, 2)).is_err());
+        assert!(jpeg.nv12(&[0; 10], (4, 2)).is_err());
+        assert!(jpeg.yuyv(&[0; 16], (4, 2)).is_ok());
+    }
+}
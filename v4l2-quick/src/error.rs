@@ -0,0 +1,117 @@
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io;
+
+use rscam::Error as V4l2Error;
+
+use controls::BadValue;
+use fourcc::FourCc;
+use ConfigSummary;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The device failed to open or to answer a query
+    V4l2(V4l2Error),
+    /// Nothing the device offers satisfies the constraints
+    NoMatch(Report),
+    /// The named control would not take the value
+    Control(String, BadValue),
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            Error::V4l2(ref err) => Display::fmt(err, fmt),
+            Error::NoMatch(ref report) => Display::fmt(report, fmt),
+            Error::Control(ref name, ref problem) => {
+                write!(fmt, "cannot set {}: {}", name, problem)
+            },
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::V4l2(ref err) => Some(err),
+            Error::NoMatch(_) | Error::Control(..) => None,
+        }
+    }
+}
+
+impl From<V4l2Error> for Error {
+    fn from(err: V4l2Error) -> Self {
+        Error::V4l2(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::V4l2(V4l2Error::Io(err))
+    }
+}
+
+impl From<Report> for Error {
+    fn from(report: Report) -> Self {
+        Error::NoMatch(report)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Reason {
+    /// Ruled out by the named constraint, like `Fmt.compress` or `Res.min`
+    Constraint(&'static str),
+    /// The device failed to list its resolutions or intervals
+    Enumeration(String),
+    /// The driver advertised it, but would run this instead
+    Rewritten(ConfigSummary),
+    /// The driver failed to say what it would make of it
+    Negotiation(String),
+}
+
+/// A format, resolution or interval that was passed over.
+/// The resolution and interval are only set if they were the ones rejected.
+#[derive(Debug, Clone)]
+pub struct Rejected {
+    pub format: FourCc,
+    pub resolution: Option<(u32, u32)>,
+    pub interval: Option<(u32, u32)>,
+    pub reason: Reason,
+}
+
+/// Everything that was passed over while looking for a configuration
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub rejected: Vec<Rejected>,
+}
+
+impl Display for Report {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        if self.rejected.is_empty() {
+            return fmt.write_str("no configuration found, the device offers no formats");
+        }
+        try!(fmt.write_str("no configuration satisfies the constraints:"));
+        for rejected in &self.rejected {
+            try!(write!(fmt, "\n    {}", rejected.format));
+            if let Some((width, height)) = rejected.resolution {
+                try!(write!(fmt, " {}x{}", width, height));
+            }
+            if let Some((num, den)) = rejected.interval {
+                try!(write!(fmt, " {}/{}s", num, den));
+            }
+            try!(match rejected.reason {
+                Reason::Constraint(name) => write!(fmt, ": rejected by {}", name),
+                Reason::Enumeration(ref err) => write!(fmt, ": could not enumerate, {}", err),
+                Reason::Negotiation(ref err) => write!(fmt, ": could not negotiate, {}", err),
+                Reason::Rewritten(ref config) => {
+                    write!(fmt, ": the driver would use {} {}x{} {}/{}s instead", config.format,
+                           config.resolution.0, config.resolution.1,
+                           config.interval.0, config.interval.1)
+                },
+            });
+        }
+        Ok(())
+    }
+}
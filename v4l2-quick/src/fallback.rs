@@ -0,0 +1,160 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use rscam::Error as V4l2Error;
+
+use error::{Error, Report};
+use source::CaptureSource;
+use {start, ConfigSummary, V4l2Result};
+
+const EAGAIN: i32 = 11;
+const EBUSY: i32 = 16;
+const ENOSPC: i32 = 28;
+
+/// How `start_first` deals with failures that may pass, like another
+/// process holding the device or a USB bus out of bandwidth
+#[derive(Clone, Debug)]
+pub struct Retry {
+    /// Tries per configuration, counting the first
+    pub attempts: u32,
+    /// The wait before the first retry, doubled before each one after it
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 3,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A source that is streaming, and how it got there
+pub struct Started<S> {
+    pub source: S,
+    pub config: ConfigSummary,
+    /// Every attempt that failed before it, in order
+    pub failures: Vec<(ConfigSummary, V4l2Error)>,
+}
+
+/// Whether trying again later could work
+fn is_transient(err: &V4l2Error) -> bool {
+    match *err {
+        V4l2Error::Io(ref err) => match err.raw_os_error() {
+            Some(EAGAIN) | Some(EBUSY) | Some(ENOSPC) => true,
+            _ => err.kind() == io::ErrorKind::Interrupted,
+        },
+        _ => false,
+    }
+}
+
+/// Starts the first of `configs`, best first, that works on a source from
+/// `open`. A source is opened for each attempt, since one that failed to
+/// start can be left half set up. Transient failures are retried as `retry`
+/// says before moving on, anything else moves on to the next configuration.
+/// Fails with the last error if none of them start.
+pub fn start_first<S, F, I>(mut open: F, configs: I, retry: &Retry) -> Result<Started<S>, Error>
+where S: CaptureSource, F: FnMut() -> V4l2Result<S>, I: IntoIterator<Item = ConfigSummary> {
+    let mut failures = Vec::new();
+    for config in configs {
+        let mut delay = retry.delay;
+        for attempt in 1..retry.attempts.max(1) + 1 {
+            let result = open().and_then(|mut source| {
+                start(&mut source, &config).map(|()| source)
+            });
+            let err = match result {
+                Ok(source) => {
+                    return Ok(Started { source: source, config: config, failures: failures });
+                },
+                Err(err) => err,
+            };
+            let again = is_transient(&err) && attempt < retry.attempts;
+            failures.push((config.clone(), err));
+            if !again {
+                break;
+            }
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+    match failures.pop() {
+        Some((_, err)) => Err(Error::V4l2(err)),
+        None => Err(Error::NoMatch(Report::default())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{caps, MockCamera};
+    use {search, Constraints, FourCc};
+
+    const EINVAL: i32 = 22;
+
+    fn camera(start_errors: &[i32]) -> MockCamera {
+        let sizes = [(640, 480), (320, 240)];
+        let mut camera = MockCamera::new(vec![caps(FourCc::YUYV, false, &sizes, &[(1, 30)])],
+                                         Vec::new());
+        camera.start_errors = start_errors.to_vec();
+        camera
+    }
+
+    fn quick(attempts: u32) -> Retry {
+        Retry {
+            attempts: attempts,
+            delay: Duration::from_millis(0),
+        }
+    }
+
+    /// Opens the same mock every time, so its scripted errors run out
+    fn start_with(start_errors: &[i32], retry: &Retry) -> Result<Started<MockCamera>, Error> {
+        let mut shared = camera(start_errors);
+        let configs = search(&shared, Constraints::default()).unwrap();
+        start_first(|| {
+            let source = shared.clone();
+            // Each open sees the errors left after the last one
+            if !shared.start_errors.is_empty() {
+                shared.start_errors.remove(0);
+            }
+            Ok(source)
+        }, configs, retry)
+    }
+
+    #[test]
+    fn retries_a_busy_device() {
+        let started = start_with(&[EBUSY, EBUSY], &quick(3)).ok().unwrap();
+        assert_eq!(started.config.resolution, (640, 480));
+        assert_eq!(started.source.started, Some((FourCc::YUYV, (640, 480), (1, 30))));
+        assert_eq!(started.failures.len(), 2);
+        assert!(started.failures.iter().all(|(config, _)| config.resolution == (640, 480)));
+    }
+
+    #[test]
+    fn moves_on_when_retries_run_out() {
+        let started = start_with(&[EBUSY, EBUSY], &quick(2)).ok().unwrap();
+        assert_eq!(started.config.resolution, (320, 240));
+        assert_eq!(started.failures.len(), 2);
+    }
+
+    #[test]
+    fn moves_on_without_retrying_other_errors() {
+        let started = start_with(&[EINVAL], &quick(3)).ok().unwrap();
+        assert_eq!(started.config.resolution, (320, 240));
+        assert_eq!(started.failures.len(), 1);
+    }
+
+    #[test]
+    fn fails_with_the_last_error() {
+        match start_with(&[EINVAL, EBUSY], &quick(1)) {
+            Err(Error::V4l2(V4l2Error::Io(err))) => assert_eq!(err.raw_os_error(), Some(EBUSY)),
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("started"),
+        }
+        match start_first(|| Ok(camera(&[])), Vec::new(), &quick(1)) {
+            Err(Error::NoMatch(report)) => assert!(report.rejected.is_empty()),
+            _ => panic!("expected no configurations"),
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::raw::{c_int, c_long, c_ulong, c_void};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::time::Duration;
+
+pub const VIDIOC_QUERYCAP: c_ulong = 0x80685600;
+pub const VIDIOC_ENUM_FMT: c_ulong = 0xC0405602;
+pub const VIDIOC_G_PARM: c_ulong = 0xC0CC5615;
+#[cfg(target_pointer_width = "64")]
+pub const VIDIOC_TRY_FMT: c_ulong = 0xC0D05640;
+#[cfg(target_pointer_width = "32")]
+pub const VIDIOC_TRY_FMT: c_ulong = 0xC0CC5640;
+#[cfg(target_pointer_width = "64")]
+pub const VIDIOC_S_FMT: c_ulong = 0xC0D05605;
+#[cfg(target_pointer_width = "32")]
+pub const VIDIOC_S_FMT: c_ulong = 0xC0CC5605;
+pub const VIDIOC_S_PARM: c_ulong = 0xC0CC5616;
+pub const VIDIOC_REQBUFS: c_ulong = 0xC0145608;
+#[cfg(target_pointer_width = "64")]
+pub const VIDIOC_QUERYBUF: c_ulong = 0xC0585609;
+#[cfg(target_pointer_width = "32")]
+pub const VIDIOC_QUERYBUF: c_ulong = 0xC0445609;
+#[cfg(target_pointer_width = "64")]
+pub const VIDIOC_QBUF: c_ulong = 0xC058560F;
+#[cfg(target_pointer_width = "32")]
+pub const VIDIOC_QBUF: c_ulong = 0xC044560F;
+#[cfg(target_pointer_width = "64")]
+pub const VIDIOC_DQBUF: c_ulong = 0xC0585611;
+#[cfg(target_pointer_width = "32")]
+pub const VIDIOC_DQBUF: c_ulong = 0xC0445611;
+pub const VIDIOC_STREAMON: c_ulong = 0x40045612;
+pub const VIDIOC_STREAMOFF: c_ulong = 0x40045613;
+
+pub const EINVAL: i32 = 22;
+pub const ENOTTY: i32 = 25;
+
+pub const BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+pub const MEMORY_MMAP: u32 = 1;
+/// Set on buffers the driver filled, but may have got wrong
+pub const BUF_FLAG_ERROR: u32 = 0x40;
+/// The bits of a buffer's flags that say which clock stamped it
+pub const BUF_FLAG_TIMESTAMP_MASK: u32 = 0xE000;
+pub const BUF_FLAG_TIMESTAMP_MONOTONIC: u32 = 0x2000;
+/// The clock V4L2 stamps buffers with
+pub const CLOCK_MONOTONIC: c_int = 1;
+/// Set in `CaptureParm::capability` if the frame interval can be set
+pub const CAP_TIMEPERFRAME: u32 = 0x1000;
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 1;
+
+// rscam keeps its descriptor to itself, so queries it doesn't wrap go
+// through a descriptor of our own
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, argp: *mut c_void) -> c_int;
+    fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long)
+        -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+/// struct timespec
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: c_long,
+    pub tv_nsec: c_long,
+}
+
+/// struct timeval
+#[repr(C)]
+pub struct Timeval {
+    pub tv_sec: c_long,
+    pub tv_usec: c_long,
+}
+
+/// struct v4l2_capability
+#[repr(C)]
+pub struct Capability {
+    pub driver: [u8; 16],
+    pub card: [u8; 32],
+    pub bus_info: [u8; 32],
+    pub version: u32,
+    pub capabilities: u32,
+    pub device_caps: u32,
+    pub reserved: [u32; 3],
+}
+
+/// struct v4l2_fmtdesc
+#[repr(C)]
+pub struct FmtDesc {
+    pub index: u32,
+    pub kind: u32,
+    pub flags: u32,
+    pub description: [u8; 32],
+    pub pixelformat: [u8; 4],
+    pub mbus_code: u32,
+    pub reserved: [u32; 3],
+}
+
+/// struct v4l2_pix_format
+#[repr(C)]
+pub struct PixFormat {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: [u8; 4],
+    pub field: u32,
+    pub bytesperline: u32,
+    pub sizeimage: u32,
+    pub colorspace: u32,
+    pub private: u32,
+    pub flags: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+    pub xfer_func: u32,
+}
+
+/// struct v4l2_format, with the union as the single planar capture format.
+/// The union holds pointers, so on 64 bit it is aligned to 8.
+#[repr(C)]
+pub struct Format {
+    pub kind: u32,
+    #[cfg(target_pointer_width = "64")]
+    pub padding: u32,
+    pub pix: PixFormat,
+    pub space: [u8; 152],
+}
+
+/// struct v4l2_captureparm
+#[repr(C)]
+pub struct CaptureParm {
+    pub capability: u32,
+    pub capturemode: u32,
+    pub timeperframe: (u32, u32),
+    pub extendedmode: u32,
+    pub readbuffers: u32,
+    pub reserved: [u32; 4],
+}
+
+/// struct v4l2_streamparm, with the union as the capture parameters
+#[repr(C)]
+pub struct StreamParm {
+    pub kind: u32,
+    pub parm: CaptureParm,
+    pub space: [u8; 160],
+}
+
+/// struct v4l2_requestbuffers
+#[repr(C)]
+pub struct RequestBuffers {
+    pub count: u32,
+    pub kind: u32,
+    pub memory: u32,
+    pub capabilities: u32,
+    pub reserved: u32,
+}
+
+/// struct v4l2_timecode
+#[repr(C)]
+pub struct Timecode {
+    pub kind: u32,
+    pub flags: u32,
+    pub frames: u8,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub userbits: [u8; 4],
+}
+
+/// struct v4l2_buffer, with the memory union as the offset of an mmap buffer.
+/// The union also holds pointers, so it takes their size.
+#[repr(C)]
+pub struct V4l2Buffer {
+    pub index: u32,
+    pub kind: u32,
+    pub bytesused: u32,
+    pub flags: u32,
+    pub field: u32,
+    pub timestamp: Timeval,
+    pub timecode: Timecode,
+    pub sequence: u32,
+    pub memory: u32,
+    pub offset: usize,
+    pub length: u32,
+    pub reserved2: u32,
+    pub request_fd: u32,
+}
+
+impl V4l2Buffer {
+    /// A capture buffer at `index` of those mapped from the driver
+    pub fn mmap(index: u32) -> Self {
+        let mut buf: V4l2Buffer = unsafe { mem::zeroed() };
+        buf.index = index;
+        buf.kind = BUF_TYPE_VIDEO_CAPTURE;
+        buf.memory = MEMORY_MMAP;
+        buf
+    }
+}
+
+/// Memory the driver shares with us, unmapped once dropped
+pub struct Region {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+impl Region {
+    /// Maps `len` bytes of `file` at `offset`, where QUERYBUF says a buffer is
+    pub fn map(file: &File, len: usize, offset: usize) -> io::Result<Self> {
+        let ptr = unsafe {
+            mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_SHARED, file.as_raw_fd(),
+                 offset as c_long)
+        };
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Region { ptr: ptr as *mut u8, len: len })
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr as *mut c_void, self.len) };
+    }
+}
+
+/// Runs `request` on `file`, retrying if a signal interrupts it
+pub fn xioctl<T>(file: &File, request: c_ulong, arg: &mut T) -> io::Result<()> {
+    loop {
+        let ret = unsafe { ioctl(file.as_raw_fd(), request, arg as *mut T as *mut c_void) };
+        if ret != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+pub fn query_cap(file: &File) -> io::Result<Capability> {
+    let mut cap: Capability = unsafe { mem::zeroed() };
+    try!(xioctl(file, VIDIOC_QUERYCAP, &mut cap));
+    Ok(cap)
+}
+
+/// The formats the driver itself has, without those libv4l2 emulates
+pub fn enum_fmt(file: &File) -> io::Result<Vec<[u8; 4]>> {
+    let mut formats = Vec::new();
+    loop {
+        let mut desc: FmtDesc = unsafe { mem::zeroed() };
+        desc.index = formats.len() as u32;
+        desc.kind = BUF_TYPE_VIDEO_CAPTURE;
+        match xioctl(file, VIDIOC_ENUM_FMT, &mut desc) {
+            Ok(()) => formats.push(desc.pixelformat),
+            // Past the last one
+            Err(ref err) if err.raw_os_error() == Some(EINVAL) => return Ok(formats),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// What the driver would make of `format`, without setting anything
+pub fn try_fmt(file: &File, resolution: (u32, u32), format: [u8; 4], field: u32)
+        -> io::Result<PixFormat> {
+    let mut fmt: Format = unsafe { mem::zeroed() };
+    fmt.kind = BUF_TYPE_VIDEO_CAPTURE;
+    fmt.pix.width = resolution.0;
+    fmt.pix.height = resolution.1;
+    fmt.pix.pixelformat = format;
+    fmt.pix.field = field;
+    try!(xioctl(file, VIDIOC_TRY_FMT, &mut fmt));
+    Ok(fmt.pix)
+}
+
+/// Sets `format`, the driver changes what it can't do to what it can
+pub fn s_fmt(file: &File, resolution: (u32, u32), format: [u8; 4], field: u32)
+        -> io::Result<PixFormat> {
+    let mut fmt: Format = unsafe { mem::zeroed() };
+    fmt.kind = BUF_TYPE_VIDEO_CAPTURE;
+    fmt.pix.width = resolution.0;
+    fmt.pix.height = resolution.1;
+    fmt.pix.pixelformat = format;
+    fmt.pix.field = field;
+    try!(xioctl(file, VIDIOC_S_FMT, &mut fmt));
+    Ok(fmt.pix)
+}
+
+/// Sets the frame interval, returning the one the driver picked
+pub fn s_parm(file: &File, interval: (u32, u32)) -> io::Result<(u32, u32)> {
+    let mut parm: StreamParm = unsafe { mem::zeroed() };
+    parm.kind = BUF_TYPE_VIDEO_CAPTURE;
+    parm.parm.timeperframe = interval;
+    try!(xioctl(file, VIDIOC_S_PARM, &mut parm));
+    Ok(parm.parm.timeperframe)
+}
+
+/// Asks for `count` mmap buffers, returning how many the driver made
+pub fn reqbufs(file: &File, count: u32) -> io::Result<u32> {
+    let mut req: RequestBuffers = unsafe { mem::zeroed() };
+    req.count = count;
+    req.kind = BUF_TYPE_VIDEO_CAPTURE;
+    req.memory = MEMORY_MMAP;
+    try!(xioctl(file, VIDIOC_REQBUFS, &mut req));
+    Ok(req.count)
+}
+
+pub fn stream(file: &File, on: bool) -> io::Result<()> {
+    let mut kind = BUF_TYPE_VIDEO_CAPTURE as c_int;
+    xioctl(file, if on { VIDIOC_STREAMON } else { VIDIOC_STREAMOFF }, &mut kind)
+}
+
+pub fn g_parm(file: &File) -> io::Result<CaptureParm> {
+    let mut parm: StreamParm = unsafe { mem::zeroed() };
+    parm.kind = BUF_TYPE_VIDEO_CAPTURE;
+    try!(xioctl(file, VIDIOC_G_PARM, &mut parm));
+    Ok(parm.parm)
+}
+
+/// The time on `CLOCK_MONOTONIC`, since boot
+pub fn monotonic() -> Duration {
+    let mut time = Timespec { tv_sec: 0, tv_nsec: 0 };
+    // Only fails for clocks that don't exist
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// The text of a NUL padded string field
+pub fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A request number as videodev2.h builds it, from the direction bits,
+    /// the number and the size of what it reads or writes
+    fn request(dir: c_ulong, nr: c_ulong, size: usize) -> c_ulong {
+        dir << 30 | (size as c_ulong) << 16 | (b'V' as c_ulong) << 8 | nr
+    }
+
+    #[test]
+    fn requests_match_the_structs() {
+        let (write, read_write) = (1, 3);
+        assert_eq!(VIDIOC_QUERYCAP, request(2, 0, mem::size_of::<Capability>()));
+        assert_eq!(VIDIOC_ENUM_FMT, request(read_write, 2, mem::size_of::<FmtDesc>()));
+        assert_eq!(VIDIOC_S_FMT, request(read_write, 5, mem::size_of::<Format>()));
+        assert_eq!(VIDIOC_TRY_FMT, request(read_write, 64, mem::size_of::<Format>()));
+        assert_eq!(VIDIOC_G_PARM, request(read_write, 21, mem::size_of::<StreamParm>()));
+        assert_eq!(VIDIOC_S_PARM, request(read_write, 22, mem::size_of::<StreamParm>()));
+        assert_eq!(VIDIOC_REQBUFS, request(read_write, 8, mem::size_of::<RequestBuffers>()));
+        let buffer = mem::size_of::<V4l2Buffer>();
+        assert_eq!(VIDIOC_QUERYBUF, request(read_write, 9, buffer));
+        assert_eq!(VIDIOC_QBUF, request(read_write, 15, buffer));
+        assert_eq!(VIDIOC_DQBUF, request(read_write, 17, buffer));
+        assert_eq!(VIDIOC_STREAMON, request(write, 18, mem::size_of::<c_int>()));
+        assert_eq!(VIDIOC_STREAMOFF, request(write, 19, mem::size_of::<c_int>()));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn buffer_layout() {
+        let buffer = V4l2Buffer::mmap(3);
+        let at = |field: *const u8| field as usize - &buffer as *const V4l2Buffer as usize;
+        assert_eq!(mem::size_of::<V4l2Buffer>(), 88);
+        assert_eq!(at(&buffer.timestamp as *const Timeval as *const u8), 24);
+        assert_eq!(at(&buffer.sequence as *const u32 as *const u8), 56);
+        assert_eq!(at(&buffer.offset as *const usize as *const u8), 64);
+        assert_eq!((buffer.index, buffer.kind, buffer.memory), (3, 1, 1));
+    }
+}
@@ -0,0 +1,215 @@
+use std::error::Error as StdError;
+use std::fmt::{Debug, Display};
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error as DeError;
+
+/// A four character code naming a pixel format, like `MJPG` or `YUYV`.
+/// Codes shorter than four characters, like `Y16 `, are padded with spaces.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FourCc(pub [u8; 4]);
+
+/// What is known about a pixel format
+#[derive(Clone, Debug)]
+pub struct FormatMeta {
+    pub fourcc: FourCc,
+    pub description: &'static str,
+    pub compressed: bool,
+    /// Average bits per pixel, `None` for compressed formats
+    pub bits_per_pixel: Option<u32>,
+    /// Whether the buffer holds separate planes, like the Y then UV of NV12
+    pub planar: bool,
+}
+
+macro_rules! formats {
+    ($($name:ident = $code:expr, $description:expr, $compressed:expr, $bpp:expr,
+       $planar:expr;)*) => {
+        impl FourCc {
+            $(pub const $name: FourCc = FourCc(*$code);)*
+        }
+
+        const KNOWN: &'static [FormatMeta] = &[
+            $(FormatMeta {
+                fourcc: FourCc(*$code),
+                description: $description,
+                compressed: $compressed,
+                bits_per_pixel: $bpp,
+                planar: $planar,
+            },)*
+        ];
+    }
+}
+
+formats! {
+    MJPG = b"MJPG", "Motion-JPEG", true, None, false;
+    JPEG = b"JPEG", "JFIF JPEG", true, None, false;
+    H264 = b"H264", "H.264", true, None, false;
+    HEVC = b"HEVC", "H.265", true, None, false;
+    YUYV = b"YUYV", "YUYV 4:2:2", false, Some(16), false;
+    YVYU = b"YVYU", "YVYU 4:2:2", false, Some(16), false;
+    UYVY = b"UYVY", "UYVY 4:2:2", false, Some(16), false;
+    VYUY = b"VYUY", "VYUY 4:2:2", false, Some(16), false;
+    NV12 = b"NV12", "Y/CbCr 4:2:0", false, Some(12), true;
+    NV21 = b"NV21", "Y/CrCb 4:2:0", false, Some(12), true;
+    NV16 = b"NV16", "Y/CbCr 4:2:2", false, Some(16), true;
+    YU12 = b"YU12", "Planar YUV 4:2:0", false, Some(12), true;
+    YV12 = b"YV12", "Planar YVU 4:2:0", false, Some(12), true;
+    RGB3 = b"RGB3", "24-bit RGB 8-8-8", false, Some(24), false;
+    BGR3 = b"BGR3", "24-bit BGR 8-8-8", false, Some(24), false;
+    RGBP = b"RGBP", "16-bit RGB 5-6-5", false, Some(16), false;
+    AR24 = b"AR24", "32-bit BGRA 8-8-8-8", false, Some(32), false;
+    XR24 = b"XR24", "32-bit BGRX 8-8-8-8", false, Some(32), false;
+    GREY = b"GREY", "8-bit Greyscale", false, Some(8), false;
+    Y10 = b"Y10 ", "10-bit Greyscale", false, Some(16), false;
+    Y12 = b"Y12 ", "12-bit Greyscale", false, Some(16), false;
+    Y14 = b"Y14 ", "14-bit Greyscale", false, Some(16), false;
+    Y16 = b"Y16 ", "16-bit Greyscale", false, Some(16), false;
+    Z16 = b"Z16 ", "16-bit Depth", false, Some(16), false;
+    BA81 = b"BA81", "8-bit Bayer BGBG/GRGR", false, Some(8), false;
+    GBRG = b"GBRG", "8-bit Bayer GBGB/RGRG", false, Some(8), false;
+    GRBG = b"GRBG", "8-bit Bayer GRGR/BGBG", false, Some(8), false;
+    RGGB = b"RGGB", "8-bit Bayer RGRG/GBGB", false, Some(8), false;
+}
+
+impl FourCc {
+    /// What is known about the format, if it is a common one
+    pub fn meta(&self) -> Option<&'static FormatMeta> {
+        KNOWN.iter().find(|meta| meta.fourcc == *self)
+    }
+
+    /// Whether it is known to be compressed, `None` if the format is unknown
+    pub fn is_compressed(&self) -> Option<bool> {
+        self.meta().map(|meta| meta.compressed)
+    }
+
+    pub fn bits_per_pixel(&self) -> Option<u32> {
+        self.meta().and_then(|meta| meta.bits_per_pixel)
+    }
+
+    /// Whether it is known to be planar, `None` if the format is unknown
+    pub fn is_planar(&self) -> Option<bool> {
+        self.meta().map(|meta| meta.planar)
+    }
+
+    /// Every format with known metadata
+    pub fn known() -> &'static [FormatMeta] {
+        KNOWN
+    }
+}
+
+impl From<[u8; 4]> for FourCc {
+    fn from(code: [u8; 4]) -> Self {
+        FourCc(code)
+    }
+}
+
+impl<'a> From<&'a [u8; 4]> for FourCc {
+    fn from(code: &'a [u8; 4]) -> Self {
+        FourCc(*code)
+    }
+}
+
+impl PartialEq<[u8; 4]> for FourCc {
+    fn eq(&self, other: &[u8; 4]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Display for FourCc {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        // The padding is not part of the name
+        fmt.write_str(String::from_utf8_lossy(&self.0).trim_end_matches(' '))
+    }
+}
+
+impl Debug for FourCc {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "FourCc({})", self)
+    }
+}
+
+/// A string that is not a FourCC
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseFourCcError(String);
+
+impl Display for ParseFourCcError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "\"{}\" is not a FourCC, those are one to four ASCII characters", self.0)
+    }
+}
+
+impl StdError for ParseFourCcError {}
+
+impl FromStr for FourCc {
+    type Err = ParseFourCcError;
+
+    fn from_str(text: &str) -> Result<Self, ParseFourCcError> {
+        let bytes = text.as_bytes();
+        if bytes.is_empty() || bytes.len() > 4 || !text.is_ascii() {
+            return Err(ParseFourCcError(text.to_string()));
+        }
+        let mut code = [b' '; 4];
+        code[..bytes.len()].copy_from_slice(bytes);
+        Ok(FourCc(code))
+    }
+}
+
+impl Serialize for FourCc {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FourCc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = try!(String::deserialize(deserializer));
+        text.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn parse_pads_short_codes() {
+        assert_eq!("MJPG".parse(), Ok(FourCc::MJPG));
+        assert_eq!("Y16".parse(), Ok(FourCc::Y16));
+        assert_eq!("Y16 ".parse(), Ok(FourCc::Y16));
+        assert_eq!("a".parse(), Ok(FourCc(*b"a   ")));
+    }
+
+    #[test]
+    fn parse_rejects_what_is_not_a_fourcc() {
+        for text in &["", "MJPEG", "Y16  ", "YÜYV", "ÿ"] {
+            assert_eq!(text.parse::<FourCc>(), Err(ParseFourCcError(text.to_string())));
+        }
+        // Fits in four bytes, but not as four characters
+        assert!("ÜY".parse::<FourCc>().is_err());
+    }
+
+    #[test]
+    fn display_drops_the_padding() {
+        assert_eq!(FourCc::Y16.to_string(), "Y16");
+        assert_eq!(FourCc::NV12.to_string(), "NV12");
+        assert_eq!(format!("{:?}", FourCc::Z16), "FourCc(Z16)");
+        // Only trailing spaces are padding
+        assert_eq!(FourCc(*b" AB ").to_string(), " AB");
+        assert_eq!(FourCc(*b"Y\xFF16").to_string(), "Y\u{FFFD}16");
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        for &fourcc in &[FourCc::MJPG, FourCc::Y10, FourCc(*b"a   ")] {
+            let json = serde_json::to_string(&fourcc).unwrap();
+            assert_eq!(json, format!("\"{}\"", fourcc));
+            assert_eq!(serde_json::from_str::<FourCc>(&json).unwrap(), fourcc);
+        }
+        assert!(serde_json::from_str::<FourCc>("\"MJPEG\"").is_err());
+        assert!(serde_json::from_str::<FourCc>("4").is_err());
+    }
+}
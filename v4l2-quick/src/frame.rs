@@ -0,0 +1,105 @@
+use std::ops::Deref;
+use std::time::Duration;
+
+use ffi;
+use fourcc::FourCc;
+use source::Buffer;
+
+/// A captured frame, what it is and, when the source knows, when it came
+pub struct Frame {
+    pub data: Buffer,
+    /// The format of `data`, which is JPEG for frames `ToJpeg` compressed
+    pub format: FourCc,
+    pub resolution: (u32, u32),
+    /// When the driver stamped it on `CLOCK_MONOTONIC`, since boot.
+    /// `None` for sources that don't say, or drivers using another clock.
+    pub timestamp: Option<Duration>,
+    /// The driver's count of frames since streaming started, a gap means it
+    /// dropped some. `None` for sources that don't count.
+    pub sequence: Option<u32>,
+    /// The `V4L2_BUF_FLAG_*` bits the driver set, 0 for sources without any
+    pub flags: u32,
+}
+
+impl Frame {
+    /// A frame without a timestamp, sequence or flags
+    pub fn new(data: Buffer, format: FourCc, resolution: (u32, u32)) -> Self {
+        Frame {
+            data: data,
+            format: format,
+            resolution: resolution,
+            timestamp: None,
+            sequence: None,
+            flags: 0,
+        }
+    }
+
+    /// How long ago the driver stamped it, if it did
+    pub fn age(&self) -> Option<Duration> {
+        let now = ffi::monotonic();
+        self.timestamp.map(|timestamp| {
+            if now > timestamp { now - timestamp } else { Duration::new(0, 0) }
+        })
+    }
+
+    /// Whether the driver flagged it as damaged, like by a glitch on the bus
+    pub fn is_damaged(&self) -> bool {
+        self.flags & ffi::BUF_FLAG_ERROR != 0
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode::ToJpeg;
+    use mock::{caps, summary, MockCamera};
+    use pattern::TestPattern;
+    use source::CaptureSource;
+    use start;
+
+    fn mock(format: FourCc, frame: Vec<u8>) -> MockCamera {
+        MockCamera::new(vec![caps(format, false, &[(2, 2)], &[(1, 30)])], vec![frame])
+    }
+
+    #[test]
+    fn frames_say_what_they_hold() {
+        let mut camera = mock(FourCc::YUYV, vec![128; 8]);
+        start(&mut camera, &summary(FourCc::YUYV, (2, 2), (1, 30))).unwrap();
+        let frame = camera.capture().unwrap();
+        assert_eq!((frame.format, frame.resolution), (FourCc::YUYV, (2, 2)));
+        assert_eq!((frame.timestamp, frame.sequence, frame.age()), (None, None, None));
+        assert!(!frame.is_damaged());
+        // Once compressed they are JPEGs
+        let mut jpeg = ToJpeg::new(mock(FourCc::YUYV, vec![128; 8]));
+        start(&mut jpeg, &summary(FourCc::YUYV, (2, 2), (1, 30))).unwrap();
+        let frame = jpeg.capture().unwrap();
+        assert_eq!((frame.format, frame.resolution), (FourCc::JPEG, (2, 2)));
+        assert_eq!(frame[..2], [0xFF, 0xD8]);
+        // Unless they already were
+        let mut jpeg = ToJpeg::new(mock(FourCc::MJPG, vec![0xFF, 0xD8, 0xFF, 0xD9]));
+        start(&mut jpeg, &summary(FourCc::MJPG, (2, 2), (1, 30))).unwrap();
+        assert_eq!(jpeg.capture().unwrap().format, FourCc::MJPG);
+    }
+
+    #[test]
+    fn pattern_frames_are_stamped_and_counted() {
+        let mut pattern = TestPattern::new();
+        start(&mut pattern, &summary(FourCc::YUYV, (32, 16), (1, 120))).unwrap();
+        let first = pattern.capture().unwrap();
+        let second = pattern.capture().unwrap();
+        assert_eq!((first.sequence, second.sequence), (Some(0), Some(1)));
+        assert!(second.timestamp > first.timestamp);
+        assert!(first.age().unwrap() < Duration::from_secs(1));
+        let mut damaged = Frame::new(Buffer::Owned(Vec::new()), FourCc::GREY, (0, 0));
+        damaged.flags = ffi::BUF_FLAG_ERROR;
+        assert!(damaged.is_damaged());
+    }
+}
@@ -0,0 +1,158 @@
+use std::io;
+
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+fn encode(data: &[u8], (width, height): (u32, u32), quality: u8, color: ColorType,
+          sampling: SamplingFactor) -> io::Result<Vec<u8>> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "too large for a JPEG"));
+    }
+    let mut out = Vec::new();
+    let mut encoder = Encoder::new(&mut out, quality);
+    encoder.set_sampling_factor(sampling);
+    try!(encoder.encode(data, width as u16, height as u16, color)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())));
+    Ok(out)
+}
+
+/// Compresses packed 8-bit RGB into a baseline JPEG
+pub fn encode_rgb(rgb: &[u8], resolution: (u32, u32), quality: u8) -> io::Result<Vec<u8>> {
+    encode(rgb, resolution, quality, ColorType::Rgb, SamplingFactor::R_4_2_0)
+}
+
+/// Compresses packed full range YCbCr, a sample of each per pixel, into a
+/// baseline JPEG. `sampling` should be what the chroma was before it was
+/// spread over every pixel, so no more detail is kept than there was.
+pub fn encode_ycbcr(ycbcr: &[u8], resolution: (u32, u32), quality: u8,
+                    sampling: SamplingFactor) -> io::Result<Vec<u8>> {
+    encode(ycbcr, resolution, quality, ColorType::Ycbcr, sampling)
+}
+
+/// The width and height in the start-of-frame segment of `jpeg`
+pub fn dimensions(jpeg: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    while at + 4 <= jpeg.len() && jpeg[at] == 0xFF {
+        let marker = jpeg[at + 1];
+        let length = (jpeg[at + 2] as usize) << 8 | jpeg[at + 3] as usize;
+        match marker {
+            // Fill bytes may pad out any marker
+            0xFF => {
+                at += 1;
+                continue;
+            },
+            // SOF0 through SOF15, less DHT, JPG and DAC which share the range
+            0xC0..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                if at + 9 > jpeg.len() {
+                    return None;
+                }
+                let height = (jpeg[at + 5] as u32) << 8 | jpeg[at + 6] as u32;
+                let width = (jpeg[at + 7] as u32) << 8 | jpeg[at + 8] as u32;
+                return Some((width, height));
+            },
+            // The image data starts without a frame header
+            0xDA => return None,
+            _ => at += 2 + length,
+        }
+    }
+    None
+}
+
+/// Splits concatenated JPEGs, like a raw MJPEG recording, into the byte
+/// ranges of each image. Anything between an end and the next start is dropped.
+pub fn split(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut frames = Vec::new();
+    let mut at = 0;
+    while let Some(start) = find(data, at, 0xD8) {
+        // Inside the entropy-coded data 0xFF is always followed by 0x00 or a
+        // restart marker, so the first EOI after the scan starts is the real one
+        match find(data, scan_start(data, start), 0xD9) {
+            Some(end) => {
+                frames.push((start, end + 2));
+                at = end + 2;
+            },
+            None => break,
+        }
+    }
+    frames
+}
+
+/// Where the image data of the JPEG at `start` begins, skipping headers that
+/// could hold an EOI of their own, like an EXIF thumbnail
+fn scan_start(data: &[u8], start: usize) -> usize {
+    let mut at = start + 2;
+    while at + 4 <= data.len() && data[at] == 0xFF {
+        match data[at + 1] {
+            0xFF => at += 1,
+            0xDA => return at,
+            _ => at += 2 + ((data[at + 2] as usize) << 8 | data[at + 3] as usize),
+        }
+    }
+    // Not a header we understand, settle for the first EOI
+    start + 2
+}
+
+/// The index of the next 0xFF `marker` pair at or after `from`
+fn find(data: &[u8], from: usize, marker: u8) -> Option<usize> {
+    if from >= data.len() {
+        return None;
+    }
+    data[from..].windows(2)
+        .position(|pair| pair[0] == 0xFF && pair[1] == marker)
+        .map(|offset| from + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal JPEG whose APP1 holds a thumbnail's own SOI and EOI, with
+    /// fill bytes before the frame header and a stuffed 0xFF in the scan
+    fn sample(width: u8, height: u8) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        data.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x08, 0xFF, 0xD8, 0x00, 0x00, 0xFF, 0xD9]);
+        data.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, height, 0x00, width,
+                                 0x01, 0x01, 0x11, 0x00]);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        data.extend_from_slice(&[0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn dimensions_past_fill_bytes_and_app_segments() {
+        assert_eq!(dimensions(&sample(64, 48)), Some((64, 48)));
+        let encoded = encode_rgb(&[0; 3 * 16 * 8], (16, 8), 80).unwrap();
+        assert_eq!(dimensions(&encoded), Some((16, 8)));
+        // The scan starts before any frame header
+        let mut headless = vec![0xFF, 0xD8];
+        headless.extend_from_slice(&sample(64, 48)[26..]);
+        assert_eq!(dimensions(&headless), None);
+        assert_eq!(dimensions(&[0x89, b'P', b'N', b'G']), None);
+    }
+
+    #[test]
+    fn scan_starts_after_the_headers() {
+        let data = sample(2, 2);
+        assert_eq!(scan_start(&data, 0), 26);
+        assert_eq!(&data[26..28], &[0xFF, 0xDA]);
+        // Headers it can't follow fall back to just after the SOI
+        assert_eq!(scan_start(&[0xFF, 0xD8, 0x00, 0x00, 0xFF, 0xD9], 0), 2);
+    }
+
+    #[test]
+    fn split_concatenated_streams() {
+        let (first, second) = (sample(2, 2), sample(4, 4));
+        let mut data = first.clone();
+        // Junk between frames is dropped
+        data.extend_from_slice(&[0x00, 0x01, 0x02]);
+        data.extend_from_slice(&second);
+        // As is a last frame cut short before its end
+        data.extend_from_slice(&second[..6]);
+        let at = first.len() + 3;
+        assert_eq!(split(&data), vec![(0, first.len()), (at, at + second.len())]);
+        assert_eq!(dimensions(&data[at..at + second.len()]), Some((4, 4)));
+        assert_eq!(split(&[]), Vec::new());
+    }
+}
@@ -1,30 +1,112 @@
+extern crate jpeg_encoder;
 extern crate rscam;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+mod bayer;
+mod builder;
+mod cache;
+mod caps;
+mod controls;
+mod convert;
+mod device;
+mod encode;
+mod error;
+mod fallback;
+mod fourcc;
+mod frame;
+mod ffi;
+mod jpeg;
+mod mmap;
+mod mock;
+mod normalize;
+mod pattern;
+mod preset;
+mod replay;
+mod serial;
+mod snapshot;
+mod source;
+mod verify;
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
 
 pub use self::rscam::{Camera, Config, FormatInfo, FormatIter, ResolutionInfo, IntervalInfo};
 pub use self::rscam::Result as V4l2Result;
+pub use self::rscam::Error as V4l2Error;
 pub use self::rscam::consts;
+pub use self::bayer::{debayer, Gains};
+pub use self::builder::{BuildError, ConstraintsBuilder};
+pub use self::cache::Cached;
+pub use self::caps::FormatCaps;
+pub use self::controls::{find_control, set_control};
+pub use self::controls::{BadValue, ControlInfo, ControlKind, ControlValue};
+pub use self::convert::{to_rgb, ConvertError, PixelLayout, RgbFormat};
+pub use self::device::{devices, query, Device};
+pub use self::encode::ToJpeg;
+pub use self::error::{Error, Reason, Rejected, Report};
+pub use self::fallback::{start_first, Retry, Started};
+pub use self::fourcc::{FormatMeta, FourCc, ParseFourCcError};
+pub use self::frame::Frame;
+pub use self::mmap::{DriverBuffer, MmapCamera};
+pub use self::mock::MockCamera;
+pub use self::normalize::{sample_bits, samples, Normalize, Palette, ParsePaletteError, Range};
+pub use self::pattern::TestPattern;
+pub use self::preset::{Preset, Presets, Setting};
+pub use self::replay::Replay;
+pub use self::serial::SettingsFile;
+pub use self::snapshot::Snapshot;
+pub use self::source::{Buffer, CaptureSource, Mode};
+pub use self::verify::{negotiate, search_verified, verify};
+
+/// Common capture sizes, used to pick sensible values out of stepwise ranges
+const COMMON_RESOLUTIONS: [(u32, u32); 16] = [
+    (160, 120), (176, 144), (320, 240), (352, 288),
+    (640, 360), (640, 480), (800, 600), (960, 540),
+    (1024, 768), (1280, 720), (1280, 960), (1280, 1024),
+    (1600, 1200), (1920, 1080), (2560, 1440), (3840, 2160),
+];
+
+/// Common frame intervals, used to pick sensible values out of stepwise ranges
+const COMMON_INTERVALS: [(u32, u32); 13] = [
+    (1, 1), (1, 5), (1, 10), (1, 15), (1, 20), (1, 24), (1, 25),
+    (1001, 30000), (1, 30), (1, 50), (1, 60), (1, 90), (1, 120),
+];
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum DisStepInfo {
     Discretes(Vec<(u32, u32)>),
+    /// Width and height each step independently
     Stepwise {
         min: (u32, u32),
         max: (u32, u32),
         step: (u32, u32),
     },
+    /// A single range where every bound is a (numerator, denominator) fraction
+    StepwiseFraction {
+        min: (u32, u32),
+        max: (u32, u32),
+        step: (u32, u32),
+    },
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Dir {
     Highest,
     Lowest,
+    /// Closest to the constraint's target, or `Highest` if it has none
+    Nearest,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Pref {
     Only,
     Never,
@@ -33,10 +115,13 @@ pub enum Pref {
     NoPreference,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Fmt {
     pub emulate: Pref,
     pub compress: Pref,
-    pub priorities: Option<Vec<&'static [u8; 4]>>,
+    /// Only these formats, most wanted first
+    pub priorities: Option<Vec<FourCc>>,
 }
 
 impl Default for Fmt {
@@ -49,19 +134,237 @@ impl Default for Fmt {
     }
 }
 
-#[derive(Clone)]
-struct DisStepConstraint {
+/// Resolution constraints. Every bound is checked per dimension,
+/// and resolutions are ordered by their pixel count.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Res {
     pub dir: Dir,
-    pub limit: Option<(u32, u32)>,
+    /// Smallest acceptable width and height
+    pub min: Option<(u32, u32)>,
+    /// Largest acceptable width and height
+    pub max: Option<(u32, u32)>,
+    /// Fewest acceptable pixels per frame
+    pub min_pixels: Option<u32>,
+    /// Most acceptable pixels per frame
+    pub max_pixels: Option<u32>,
+    /// Only accept this width to height ratio (within 1%), like `(16, 9)`
+    pub aspect: Option<(u32, u32)>,
+    /// The width and height `Dir::Nearest` aims for
+    pub target: Option<(u32, u32)>,
+}
+
+impl Default for Res {
+    fn default() -> Self {
+        Res {
+            dir: Dir::Highest,
+            min: None,
+            max: None,
+            min_pixels: None,
+            max_pixels: None,
+            aspect: None,
+            target: None,
+        }
+    }
+}
+
+impl Res {
+    /// How far `value` is from the target, as the sum of the log ratios of
+    /// the widths and of the heights. Halving a dimension is as far off as
+    /// doubling it, and a wrong aspect ratio costs extra.
+    pub fn distance(&self, value: (u32, u32)) -> Option<f64> {
+        self.target.map(|(width, height)| {
+            log_ratio(value.0 as f64, width as f64) + log_ratio(value.1 as f64, height as f64)
+        })
+    }
+}
+
+/// The absolute log of `a / b`, so that being twice or half as big are equally far off
+fn log_ratio(a: f64, b: f64) -> f64 {
+    if a <= 0. || b <= 0. {
+        return f64::INFINITY;
+    }
+    (a / b).ln().abs()
+}
+
+/// Orders by distance from a target, farthest first, or by `otherwise` when
+/// there is no target
+fn by_distance<D, F>(a: &(u32, u32), b: &(u32, u32), distance: D, otherwise: F) -> Ordering
+where D: Fn((u32, u32)) -> Option<f64>, F: Fn(&(u32, u32), &(u32, u32)) -> Ordering {
+    match (distance(*a), distance(*b)) {
+        (Some(da), Some(db)) => {
+            // On a tie prefer the bigger value
+            db.partial_cmp(&da).unwrap_or(Ordering::Equal).then(otherwise(a, b))
+        },
+        _ => otherwise(a, b),
+    }
+}
+
+/// Frame rate constraints, in frames per second.
+/// Intervals are compared as fractions, so `(1, 30)` and `(2, 60)` are equal.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Speed {
+    pub dir: Dir,
+    /// Slowest acceptable frame rate
+    pub min_fps: Option<f32>,
+    /// Fastest acceptable frame rate
+    pub max_fps: Option<f32>,
+    /// The frame rate `Dir::Nearest` aims for
+    pub target_fps: Option<f32>,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed {
+            dir: Dir::Highest,
+            min_fps: None,
+            max_fps: None,
+            target_fps: None,
+        }
+    }
+}
+
+impl Speed {
+    /// How far the frame rate of `interval` is from the target, as the
+    /// log ratio of the two rates
+    pub fn distance(&self, interval: (u32, u32)) -> Option<f64> {
+        self.target_fps.map(|target| {
+            log_ratio(interval.1 as f64, interval.0 as f64 * target as f64)
+        })
+    }
+}
+
+/// Decides which values a `DisStepPicker` yields, and in which order
+trait Criteria {
+    /// The name of the constraint that rules out `value`, if any
+    fn violation(&self, value: (u32, u32)) -> Option<&'static str>;
+    /// Orders values from least to most wanted
+    fn order(&self, a: &(u32, u32), b: &(u32, u32)) -> Ordering;
+    /// Values worth trying when the device only reports a stepwise range
+    fn anchors(&self) -> Vec<(u32, u32)>;
+}
+
+impl Criteria for Res {
+    fn violation(&self, value: (u32, u32)) -> Option<&'static str> {
+        let (width, height) = value;
+        let pixels = width as u64 * height as u64;
+        if self.min.is_some_and(|(w, h)| width < w || height < h) {
+            return Some("Res.min");
+        }
+        if self.max.is_some_and(|(w, h)| width > w || height > h) {
+            return Some("Res.max");
+        }
+        if self.min_pixels.is_some_and(|min| pixels < min as u64) {
+            return Some("Res.min_pixels");
+        }
+        if self.max_pixels.is_some_and(|max| pixels > max as u64) {
+            return Some("Res.max_pixels");
+        }
+        let misshapen = self.aspect.is_some_and(|(w, h)| {
+            // Cross multiply, and allow 1% for sizes like 1366x768
+            let actual = width as f64 * h as f64;
+            let wanted = height as f64 * w as f64;
+            (actual - wanted).abs() > wanted * 0.01
+        });
+        if misshapen {
+            return Some("Res.aspect");
+        }
+        None
+    }
+
+    fn order(&self, a: &(u32, u32), b: &(u32, u32)) -> Ordering {
+        let by_area = |a: &(u32, u32), b: &(u32, u32)| {
+            (a.0 as u64 * a.1 as u64).cmp(&(b.0 as u64 * b.1 as u64)).then(a.cmp(b))
+        };
+        match self.dir {
+            Dir::Highest => by_area(a, b),
+            Dir::Lowest => by_area(b, a),
+            Dir::Nearest => by_distance(a, b, |v| self.distance(v), by_area),
+        }
+    }
+
+    fn anchors(&self) -> Vec<(u32, u32)> {
+        self.min.iter().chain(self.max.iter()).chain(self.target.iter()).cloned().collect()
+    }
+}
+
+/// Compares two (numerator, denominator) intervals by the frame rate they give
+fn cmp_fps(a: &(u32, u32), b: &(u32, u32)) -> Ordering {
+    // a.1 / a.0 against b.1 / b.0, without dividing
+    let lhs = a.1 as u64 * b.0 as u64;
+    let rhs = b.1 as u64 * a.0 as u64;
+    lhs.cmp(&rhs).then(a.cmp(b))
+}
+
+/// The interval between frames at `fps` frames per second
+fn interval_of(fps: f32) -> (u32, u32) {
+    let den = (fps as f64 * 1000.).round() as u128;
+    let divisor = gcd(1000, den);
+    ((1000 / divisor) as u32, (den / divisor) as u32)
+}
+
+impl Criteria for Speed {
+    fn violation(&self, value: (u32, u32)) -> Option<&'static str> {
+        let (num, den) = (value.0 as f64, value.1 as f64);
+        if self.min_fps.is_some_and(|min| value.1 == 0 || den < min as f64 * num) {
+            return Some("Speed.min_fps");
+        }
+        if self.max_fps.is_some_and(|max| value.0 == 0 || den > max as f64 * num) {
+            return Some("Speed.max_fps");
+        }
+        None
+    }
+
+    fn order(&self, a: &(u32, u32), b: &(u32, u32)) -> Ordering {
+        match self.dir {
+            Dir::Highest => cmp_fps(a, b),
+            Dir::Lowest => cmp_fps(b, a),
+            Dir::Nearest => by_distance(a, b, |v| self.distance(v), cmp_fps),
+        }
+    }
+
+    fn anchors(&self) -> Vec<(u32, u32)> {
+        self.min_fps.iter().chain(self.max_fps.iter()).chain(self.target_fps.iter())
+            .filter(|&&fps| fps > 0.)
+            .map(|&fps| interval_of(fps))
+            .collect()
+    }
+}
+
+/// How much each criterion counts towards the score of a configuration.
+/// Every criterion is scaled to [0, 1] before it is weighted.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    /// Rank of the format in the `Fmt` preference order
+    pub format: f32,
+    /// Pixels per frame relative to the best candidate, or closeness to the target
+    pub pixels: f32,
+    /// Frames per second relative to the best candidate, or closeness to the target
+    pub fps: f32,
+    /// Whether the format agrees with `Fmt.compress`
+    pub compression: f32,
 }
 
-pub type Res = DisStepConstraint;
-pub type Speed = DisStepConstraint;
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            format: 4.,
+            pixels: 2.,
+            fps: 1.,
+            compression: 1.,
+        }
+    }
+}
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Constraints {
     pub formats: Option<Fmt>,
     pub resolutions: Option<Res>,
     pub speeds: Option<Speed>,
+    pub weights: Weights,
     pub field: u32,
     pub nbuffers: u32,
 }
@@ -72,19 +375,37 @@ impl Default for Constraints {
             formats: None,
             resolutions: None,
             speeds: None,
+            weights: Default::default(),
             field: consts::FIELD_NONE,
             nbuffers: 2,
         }
     }
 }
 
-#[derive(Clone)]
+/// Constraints by name, like the "fast" and "best" profiles of v4l2tcp,
+/// kept in a TOML or JSON file with a table per profile
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Profiles(pub BTreeMap<String, Constraints>);
+
+impl Profiles {
+    pub fn get(&self, name: &str) -> Option<&Constraints> {
+        self.0.get(name)
+    }
+}
+
+impl SettingsFile for Profiles {}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ConfigSummary {
     pub interval: (u32, u32),
     pub resolution: (u32, u32),
-    pub format: [u8; 4],
+    pub format: FourCc,
     pub field: u32,
     pub nbuffers: u32,
+    /// Bytes from the start of one row to the next, once the driver has
+    /// negotiated it. Drivers can pad rows out.
+    #[serde(default)]
+    pub stride: Option<u32>,
 }
 
 impl Debug for ConfigSummary {
@@ -95,7 +416,7 @@ impl Debug for ConfigSummary {
         try!(fmt.write_fmt(format_args!(
             "    resolution: {}x{},\n", self.resolution.0, self.resolution.1)));
         try!(fmt.write_fmt(format_args!(
-            "    picture_format: {},\n", String::from_utf8_lossy(&self.format))));
+            "    picture_format: {},\n", self.format)));
         try!(fmt.write_fmt(format_args!(
             "    field: {}\n", self.field)));
         try!(fmt.write_fmt(format_args!(
@@ -108,18 +429,25 @@ struct FormatPicker {
     formats: Vec<FormatInfo>,
     constraints: Option<Fmt>,
     sorted: bool,
+    // Rank of the next format
+    rank: usize,
+    // Formats filtered out, and the constraint that did it
+    rejected: Vec<(FourCc, &'static str)>,
 }
 
 impl FormatPicker {
-    pub fn new(formats: FormatIter, constraints: Option<Fmt>) -> Self {
+    pub fn new(formats: Vec<FormatInfo>, constraints: Option<Fmt>) -> Self {
         FormatPicker {
             constraints: constraints,
-            formats: formats.filter_map(Result::ok).collect(),
+            formats: formats,
             sorted: false,
+            rank: 0,
+            rejected: Vec::new(),
         }
     }
 
-    fn sort(constraints: &Fmt, formats: &mut Vec<FormatInfo>) {
+    fn sort(constraints: &Fmt, formats: &mut Vec<FormatInfo>,
+            rejected: &mut Vec<(FourCc, &'static str)>) {
         // This has to only be done once, it can be slow
         // for the sake of clarity
         // Filter if user cares about emulated formats
@@ -130,7 +458,11 @@ impl FormatPicker {
         };
         if let Some(emulate) = filter_emulate {
             formats.retain(|f| {
-                f.emulated && emulate || !f.emulated && !emulate
+                let keep = f.emulated && emulate || !f.emulated && !emulate;
+                if !keep {
+                    rejected.push((FourCc(f.format), "Fmt.emulate"));
+                }
+                keep
             });
         }
         // Filter is user cares about compression
@@ -141,11 +473,15 @@ impl FormatPicker {
         };
         if let Some(compress) = filter_compress {
             formats.retain(|f| {
-                f.compressed && compress || !f.compressed && !compress
+                let keep = f.compressed && compress || !f.compressed && !compress;
+                if !keep {
+                    rejected.push((FourCc(f.format), "Fmt.compress"));
+                }
+                keep
             });
         }
         // Create a map of formats to their priorities
-        let wanted: Option<HashMap<&[u8; 4], usize>> = constraints.priorities.as_ref().map(|vec| {
+        let wanted: Option<HashMap<FourCc, usize>> = constraints.priorities.as_ref().map(|vec| {
             vec.into_iter()
                 .enumerate()
                 .fold(HashMap::new(), |mut map, (index, format)| {
@@ -156,60 +492,98 @@ impl FormatPicker {
         // Remove all formats not in the priorities list
         if let Some(ref priorities) = wanted {
             formats.retain(|f| {
-                priorities.get(&f.format).is_some()
+                let keep = priorities.get(&FourCc(f.format)).is_some();
+                if !keep {
+                    rejected.push((FourCc(f.format), "Fmt.priorities"));
+                }
+                keep
             });
         }
         // Sort formats based on preferences and priorities
-        formats.sort_by(|a, b| {
-            // Sort first by priority
-            if let Some(ref priorities) = wanted {
-                let a_priority = priorities.get(&a.format);
-                let b_priority = priorities.get(&b.format);
-                if let (Some(ap), Some(bp)) = (a_priority, b_priority) {
-                    if ap < bp {
-                        return Ordering::Greater;
-                    } else if ap > bp {
-                        return Ordering::Less;
-                    }
+        formats.sort_by(|a, b| FormatPicker::compare(constraints, a, b));
+    }
+
+    /// Orders formats from least to most wanted
+    fn compare(constraints: &Fmt, a: &FormatInfo, b: &FormatInfo) -> Ordering {
+        // Sort first by priority
+        if let Some(ref priorities) = constraints.priorities {
+            let a_priority = priorities.iter().position(|&f| f == FourCc(a.format));
+            let b_priority = priorities.iter().position(|&f| f == FourCc(b.format));
+            if let (Some(ap), Some(bp)) = (a_priority, b_priority) {
+                if ap < bp {
+                    return Ordering::Greater;
+                } else if ap > bp {
+                    return Ordering::Less;
                 }
             }
-            // Next sort by emulation preferences
-            let decision = match (a.emulated, b.emulated, &constraints.emulate) {
-                (true, false, &Pref::Prefer) => Some(Ordering::Greater),
-                (false, true, &Pref::Prefer) => Some(Ordering::Less),
-                (true, false, &Pref::DoNotPrefer) => Some(Ordering::Less),
-                (false, true, &Pref::DoNotPrefer) => Some(Ordering::Greater),
-                _ => None,
-            };
-            if let Some(concrete) = decision {
-                return concrete;
-            }
-            // Finally sort by compression preferences
-            let decision = match (a.compressed, b.compressed, &constraints.compress) {
-                (true, false, &Pref::Prefer) => Some(Ordering::Greater),
-                (false, true, &Pref::Prefer) => Some(Ordering::Less),
-                (true, false, &Pref::DoNotPrefer) => Some(Ordering::Less),
-                (false, true, &Pref::DoNotPrefer) => Some(Ordering::Greater),
-                _ => None,
-            };
-            if let Some(concrete) = decision {
-                return concrete;
-            }
-            // We cannot decide! They are equal
-            Ordering::Equal
-        });
+        }
+        // Next sort by emulation preferences
+        let decision = match (a.emulated, b.emulated, &constraints.emulate) {
+            (true, false, &Pref::Prefer) => Some(Ordering::Greater),
+            (false, true, &Pref::Prefer) => Some(Ordering::Less),
+            (true, false, &Pref::DoNotPrefer) => Some(Ordering::Less),
+            (false, true, &Pref::DoNotPrefer) => Some(Ordering::Greater),
+            _ => None,
+        };
+        if let Some(concrete) = decision {
+            return concrete;
+        }
+        // Finally sort by compression preferences
+        let decision = match (a.compressed, b.compressed, &constraints.compress) {
+            (true, false, &Pref::Prefer) => Some(Ordering::Greater),
+            (false, true, &Pref::Prefer) => Some(Ordering::Less),
+            (true, false, &Pref::DoNotPrefer) => Some(Ordering::Less),
+            (false, true, &Pref::DoNotPrefer) => Some(Ordering::Greater),
+            _ => None,
+        };
+        if let Some(concrete) = decision {
+            return concrete;
+        }
+        // We cannot decide! They are equal
+        Ordering::Equal
     }
 }
 
+/// Yields formats most wanted first, each with its rank in the preference order
 impl Iterator for FormatPicker {
-    type Item = FormatInfo;
+    type Item = (usize, FormatInfo);
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(ref constraints) = self.constraints {
             if !self.sorted {
-                FormatPicker::sort(constraints, &mut self.formats);
+                FormatPicker::sort(constraints, &mut self.formats, &mut self.rejected);
+                self.sorted = true;
+            }
+        }
+        let format = self.formats.pop()?;
+        let rank = self.rank;
+        // Formats the constraints can't tell apart share a rank
+        if let (Some(constraints), Some(next)) = (self.constraints.as_ref(), self.formats.last()) {
+            if FormatPicker::compare(constraints, next, &format) != Ordering::Equal {
+                self.rank += 1;
             }
         }
-        self.formats.pop()
+        Some((rank, format))
+    }
+}
+
+impl DisStepInfo {
+    /// Whether `value` is one of the values on offer
+    pub fn contains(&self, value: (u32, u32)) -> bool {
+        let on_grid = |value: u32, min: u32, max: u32, step: u32| {
+            value >= min && value <= max && (step == 0 || (value - min).is_multiple_of(step))
+        };
+        match *self {
+            DisStepInfo::Discretes(ref discretes) => discretes.contains(&value),
+            DisStepInfo::Stepwise{min, max, step} => {
+                on_grid(value.0, min.0, max.0, step.0) && on_grid(value.1, min.1, max.1, step.1)
+            },
+            DisStepInfo::StepwiseFraction{min, max, step} => {
+                // On the grid if snapping it onto the grid changes nothing
+                snap_fraction(value, min, max, step, false).is_some_and(|snapped| {
+                    snapped.0 as u64 * value.1 as u64 == value.0 as u64 * snapped.1 as u64
+                })
+            },
+        }
     }
 }
 
@@ -233,7 +607,7 @@ impl Into<DisStepInfo> for IntervalInfo {
         match self {
             IntervalInfo::Discretes(d) => DisStepInfo::Discretes(d),
             IntervalInfo::Stepwise{min, max, step} => {
-                DisStepInfo::Stepwise {
+                DisStepInfo::StepwiseFraction {
                     min: min,
                     max: max,
                     step: step
@@ -243,96 +617,743 @@ impl Into<DisStepInfo> for IntervalInfo {
     }
 }
 
-pub struct DisStepPicker {
-    info: DisStepInfo,
-    constraints: Option<DisStepConstraint>,
+struct DisStepPicker<C> {
+    discretes: Vec<(u32, u32)>,
+    constraints: Option<C>,
     sorted: bool,
+    // Values filtered out, and the constraint that did it
+    rejected: Vec<((u32, u32), &'static str)>,
 }
 
-impl DisStepPicker {
-    fn new<I>(info: I, constraints: Option<DisStepConstraint>) -> Self
+impl<C: Criteria> DisStepPicker<C> {
+    fn new<I>(info: I, constraints: Option<C>) -> Self
     where I: Into<DisStepInfo> {
+        let anchors = constraints.as_ref().map_or(Vec::new(), |c| c.anchors());
+        // Stepwise ranges can hold millions of values, so only keep
+        // the ones someone would actually ask for
+        let discretes = match info.into() {
+            DisStepInfo::Discretes(discretes) => discretes,
+            DisStepInfo::Stepwise{min, max, step} => {
+                expand_sizes(min, max, step, &anchors)
+            },
+            DisStepInfo::StepwiseFraction{min, max, step} => {
+                expand_fractions(min, max, step, &anchors)
+            },
+        };
         DisStepPicker {
-            info: info.into(),
+            discretes: discretes,
             constraints: constraints,
             sorted: false,
+            rejected: Vec::new(),
         }
     }
 
-    fn sort_discretes(discretes: &mut Vec<(u32, u32)>, constraints: &DisStepConstraint) {
-        let want_highest = match constraints.dir {
-            Dir::Highest => true,
-            Dir::Lowest => false,
-        };
-        if let Some(ref limit) = constraints.limit {
-            discretes.retain(|r| {
-                (want_highest && r <= limit) || (!want_highest && r >= limit)
-            });
-        }
-        if want_highest {
-            discretes.sort();
-        } else {
-            // Sort in reverse
-            discretes.sort_by(|a, b| b.cmp(a));
-        }
+    fn sort_discretes(discretes: &mut Vec<(u32, u32)>, constraints: &C,
+                      rejected: &mut Vec<((u32, u32), &'static str)>) {
+        discretes.retain(|&value| {
+            match constraints.violation(value) {
+                Some(name) => {
+                    rejected.push((value, name));
+                    false
+                },
+                None => true,
+            }
+        });
+        discretes.sort_by(|a, b| constraints.order(a, b));
     }
 }
 
-impl Iterator for DisStepPicker {
+impl<C: Criteria> Iterator for DisStepPicker<C> {
     type Item = (u32, u32);
     fn next(&mut self) -> Option<Self::Item> {
-        match self.info {
-            DisStepInfo::Discretes(ref mut discretes) => {
-                if let Some(ref constraints) = self.constraints {
-                    if !self.sorted {
-                        DisStepPicker::sort_discretes(discretes, constraints);
-                    }
-                }
-                discretes.pop()
-            },
-            DisStepInfo::Stepwise{ .. } => {
-                unimplemented!();
-            },
+        if let Some(ref constraints) = self.constraints {
+            if !self.sorted {
+                DisStepPicker::sort_discretes(&mut self.discretes, constraints,
+                                              &mut self.rejected);
+                self.sorted = true;
+            }
         }
+        self.discretes.pop()
     }
 }
 
-fn get_config(camera: &Camera, reqs: Constraints) -> Option<ConfigSummary> {
-    let formats = FormatPicker::new(camera.formats(), reqs.formats);
-    for format in formats {
-        if let Ok(resolutions) = camera.resolutions(&format.format) {
-            let resolutions = DisStepPicker::new(resolutions, reqs.resolutions.clone());
-            for resolution in resolutions {
-                if let Ok(intervals) = camera.intervals(&format.format, resolution) {
-                    let intervals = DisStepPicker::new(intervals, reqs.speeds.clone());
-                    for interval in intervals {
-                        return Some(ConfigSummary {
-                            interval: interval,
-                            resolution: resolution,
-                            format: format.format,
-                            field: reqs.field,
-                            nbuffers: reqs.nbuffers,
-                        });
-                    }
-                }
+fn expand_sizes(min: (u32, u32), max: (u32, u32), step: (u32, u32),
+                anchors: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    // Move onto the grid, staying inside the range
+    let snap = |value: u32, min: u32, max: u32, step: u32, up: bool| {
+        let step = if step == 0 { 1 } else { step };
+        let value = if up { value.saturating_add(step - 1) } else { value };
+        if value <= min {
+            return min;
+        }
+        let value = if value > max { max } else { value };
+        min + (value - min) / step * step
+    };
+    let snap_size = |size: (u32, u32), up: bool| {
+        (snap(size.0, min.0, max.0, step.0, up), snap(size.1, min.1, max.1, step.1, up))
+    };
+    let mut sizes = vec![min, snap_size(max, false)];
+    for &size in COMMON_RESOLUTIONS.iter().chain(anchors) {
+        sizes.push(snap_size(size, false));
+        sizes.push(snap_size(size, true));
+    }
+    sizes.sort();
+    sizes.dedup();
+    sizes
+}
+
+fn expand_fractions(min: (u32, u32), max: (u32, u32), step: (u32, u32),
+                    anchors: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut fractions = vec![min, max];
+    for &fraction in COMMON_INTERVALS.iter().chain(anchors) {
+        for &up in &[false, true] {
+            if let Some(snapped) = snap_fraction(fraction, min, max, step, up) {
+                fractions.push(snapped);
             }
         }
     }
-    None
+    fractions.sort();
+    fractions.dedup();
+    fractions
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// `fraction` in lowest terms, widened for cross multiplying
+fn reduced(fraction: (u32, u32)) -> (u128, u128) {
+    let (num, den) = (fraction.0 as u128, fraction.1 as u128);
+    let divisor = gcd(num, den).max(1);
+    (num / divisor, den / divisor)
+}
+
+/// `num / den` in lowest terms, if it fits
+fn narrowed(num: u128, den: u128) -> Option<(u32, u32)> {
+    let divisor = gcd(num, den).max(1);
+    let (num, den) = (num / divisor, den / divisor);
+    if num > u32::MAX as u128 || den > u32::MAX as u128 {
+        return None;
+    }
+    Some((num as u32, den as u32))
 }
 
-pub fn configure(path: &str, reqs: Constraints) -> V4l2Result<Option<ConfigSummary>> {
+/// Rounds `value` down (or `up`) onto the grid `min + k * step`, keeping it
+/// within `[min, max]`. All arguments are (numerator, denominator) fractions.
+fn snap_fraction(value: (u32, u32), min: (u32, u32), max: (u32, u32), step: (u32, u32),
+                 up: bool) -> Option<(u32, u32)> {
+    if value.1 == 0 || min.1 == 0 || max.1 == 0 {
+        return None;
+    }
+    // uvcvideo counts in 100ns, like 333333/10000000, so the products
+    // below outgrow 64 bits
+    let (vn, vd) = reduced(value);
+    let (an, ad) = reduced(min);
+    let (bn, bd) = reduced(max);
+    // Clamp to the range first, a range upside down ends up at min
+    let (vn, vd) = if vn * bd > bn * vd { (bn, bd) } else { (vn, vd) };
+    if vn * ad <= an * vd {
+        return Some(min);
+    }
+    // A zero step means the range is continuous
+    if step.0 == 0 || step.1 == 0 {
+        return narrowed(vn, vd);
+    }
+    let (sn, sd) = reduced(step);
+    // floor((value - min) / step), or the ceiling when rounding up
+    let steps = |num: u128, den: u128, up: bool| {
+        let offset = (num * ad - an * den) * sd;
+        let span = den * ad * sn;
+        if up { offset.div_ceil(span) } else { offset / span }
+    };
+    let k = steps(vn, vd, up);
+    // Rounding up can step past the end of the range
+    if k > steps(bn, bd, false) {
+        return Some(max);
+    }
+    // min + k * step over their common denominator
+    let den = ad / gcd(ad, sd) * sd;
+    narrowed(an * (den / ad) + k * sn * (den / sd), den)
+}
+
+struct Candidate {
+    config: ConfigSummary,
+    // Position of the format in the preference order, shared by formats
+    // the constraints don't tell apart
+    rank: usize,
+    compressed: bool,
+}
+
+fn pixels(resolution: (u32, u32)) -> f32 {
+    resolution.0 as f32 * resolution.1 as f32
+}
+
+fn fps(interval: (u32, u32)) -> f32 {
+    if interval.0 == 0 {
+        return 0.;
+    }
+    interval.1 as f32 / interval.0 as f32
+}
+
+/// Scales `value` to [0, 1], where 1 is the best of `lowest..highest`.
+/// `Dir::Nearest` only gets here without a target, where it means `Highest`.
+fn relative(value: f32, lowest: f32, highest: f32, dir: &Dir) -> f32 {
+    match *dir {
+        Dir::Highest | Dir::Nearest if highest > 0. => value / highest,
+        Dir::Lowest if value > 0. => lowest / value,
+        _ => 1.,
+    }
+}
+
+fn bounds<F>(candidates: &[Candidate], measure: F) -> (f32, f32)
+where F: Fn(&Candidate) -> f32 {
+    candidates.iter().map(measure).fold((f32::MAX, 0.), |(lo, hi), v| {
+        (lo.min(v), hi.max(v))
+    })
+}
+
+fn enumerate<S>(camera: &S, reqs: &Constraints, report: &mut Report) -> Vec<Candidate>
+where S: CaptureSource + ?Sized {
+    let mut candidates = Vec::new();
+    let mut reject = |format: FourCc, resolution, interval, reason| {
+        report.rejected.push(Rejected {
+            format: format,
+            resolution: resolution,
+            interval: interval,
+            reason: reason,
+        });
+    };
+    let mut formats = FormatPicker::new(camera.formats(), reqs.formats.clone());
+    for (rank, info) in formats.by_ref() {
+        let format = FourCc(info.format);
+        let resolutions = match camera.resolutions(format) {
+            Ok(resolutions) => resolutions,
+            Err(err) => {
+                reject(format, None, None, Reason::Enumeration(err.to_string()));
+                continue;
+            },
+        };
+        let mut resolutions = DisStepPicker::new(resolutions, reqs.resolutions.clone());
+        for resolution in resolutions.by_ref() {
+            let intervals = match camera.intervals(format, resolution) {
+                Ok(intervals) => intervals,
+                Err(err) => {
+                    reject(format, Some(resolution), None,
+                           Reason::Enumeration(err.to_string()));
+                    continue;
+                },
+            };
+            let mut intervals = DisStepPicker::new(intervals, reqs.speeds.clone());
+            for interval in intervals.by_ref() {
+                candidates.push(Candidate {
+                    config: ConfigSummary {
+                        interval: interval,
+                        resolution: resolution,
+                        format: format,
+                        field: reqs.field,
+                        nbuffers: reqs.nbuffers,
+                        stride: None,
+                    },
+                    rank: rank,
+                    compressed: info.compressed,
+                });
+            }
+            for (interval, name) in intervals.rejected {
+                reject(format, Some(resolution), Some(interval), Reason::Constraint(name));
+            }
+        }
+        for (resolution, name) in resolutions.rejected {
+            reject(format, Some(resolution), None, Reason::Constraint(name));
+        }
+    }
+    for (format, name) in formats.rejected {
+        reject(format, None, None, Reason::Constraint(name));
+    }
+    candidates
+}
+
+fn score(candidates: &[Candidate], reqs: &Constraints) -> Vec<f32> {
+    let (min_pixels, max_pixels) = bounds(candidates, |c| pixels(c.config.resolution));
+    let (min_fps, max_fps) = bounds(candidates, |c| fps(c.config.interval));
+    let last_rank = candidates.iter().map(|c| c.rank).max().unwrap_or(0);
+    // Without constraints bigger and faster is better
+    let res_dir = reqs.resolutions.as_ref().map_or(Dir::Highest, |r| r.dir.clone());
+    let speed_dir = reqs.speeds.as_ref().map_or(Dir::Highest, |s| s.dir.clone());
+    let compress = reqs.formats.as_ref().map(|f| &f.compress);
+    // With a target, being close to it is all that counts
+    let closeness = |distance: f64| (1. / (1. + distance)) as f32;
+    let res_distance = |resolution| match reqs.resolutions {
+        Some(ref res @ Res { dir: Dir::Nearest, .. }) => res.distance(resolution),
+        _ => None,
+    };
+    let speed_distance = |interval| match reqs.speeds {
+        Some(ref speed @ Speed { dir: Dir::Nearest, .. }) => speed.distance(interval),
+        _ => None,
+    };
+    let weights = &reqs.weights;
+    candidates.iter().map(|c| {
+        let format = if last_rank == 0 {
+            1.
+        } else {
+            1. - c.rank as f32 / last_rank as f32
+        };
+        let compression = match (compress, c.compressed) {
+            (Some(&Pref::Prefer), true) | (Some(&Pref::Only), true) => 1.,
+            (Some(&Pref::DoNotPrefer), false) | (Some(&Pref::Never), false) => 1.,
+            _ => 0.,
+        };
+        let resolution = c.config.resolution;
+        let pixels = res_distance(resolution).map_or_else(|| {
+            relative(pixels(resolution), min_pixels, max_pixels, &res_dir)
+        }, &closeness);
+        let interval = c.config.interval;
+        let fps = speed_distance(interval).map_or_else(|| {
+            relative(fps(interval), min_fps, max_fps, &speed_dir)
+        }, &closeness);
+        weights.format * format
+            + weights.pixels * pixels
+            + weights.fps * fps
+            + weights.compression * compression
+    }).collect()
+}
+
+/// Every configuration that satisfies `reqs`, best first,
+/// or a report of why nothing does
+pub fn search<S>(camera: &S, reqs: Constraints) -> Result<Vec<ConfigSummary>, Report>
+where S: CaptureSource + ?Sized {
+    let mut report = Report::default();
+    let candidates = enumerate(camera, &reqs, &mut report);
+    if candidates.is_empty() {
+        return Err(report);
+    }
+    let scores = score(&candidates, &reqs);
+    let mut ranked: Vec<(f32, Candidate)> = scores.into_iter().zip(candidates).collect();
+    // The sort is stable, so on a tie the candidate enumerated first wins
+    ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    Ok(ranked.into_iter().map(|(_, candidate)| candidate.config).collect())
+}
+
+/// Every configuration that satisfies `reqs`, best first
+pub fn candidates<S>(camera: &S, reqs: Constraints) -> impl Iterator<Item = ConfigSummary>
+where S: CaptureSource + ?Sized {
+    search(camera, reqs).unwrap_or_default().into_iter()
+}
+
+/// The best configuration of the device at `path` for `reqs`, as the
+/// driver negotiates it, passing over any the driver would rewrite
+pub fn configure(path: &str, reqs: Constraints) -> Result<ConfigSummary, Error> {
     let camera = try!(Camera::new(path));
-    Ok(get_config(&camera, reqs))
+    let mut configs = try!(search_verified(&camera, path, reqs));
+    Ok(configs.remove(0))
 }
 
-pub fn start(camera: &mut Camera, reqs: &ConfigSummary) -> V4l2Result<()> {
+pub fn start<S>(camera: &mut S, reqs: &ConfigSummary) -> V4l2Result<()>
+where S: CaptureSource + ?Sized {
     try!(camera.start(&Config {
         interval: reqs.interval,
         resolution: reqs.resolution,
-        format: &reqs.format[..],
+        format: &reqs.format.0[..],
         field: reqs.field,
         nbuffers: reqs.nbuffers,
     }));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::caps;
+
+    // What uvcvideo reports for a camera doing 5 to 30 fps in steps of 100ns
+    const MIN: (u32, u32) = (333333, 10000000);
+    const MAX: (u32, u32) = (2000000, 10000000);
+    const STEP: (u32, u32) = (1, 10000000);
+
+    #[test]
+    fn snap_fraction_in_100ns_units() {
+        assert_eq!(snap_fraction((1, 30), MIN, MAX, STEP, false), Some(MIN));
+        assert_eq!(snap_fraction((1, 30), MIN, MAX, STEP, true), Some((166667, 5000000)));
+        assert_eq!(snap_fraction((1, 15), MIN, MAX, STEP, false), Some((333333, 5000000)));
+        assert_eq!(snap_fraction((1, 15), MIN, MAX, STEP, true), Some((666667, 10000000)));
+        assert_eq!(snap_fraction((1, 5), MIN, MAX, STEP, true), Some((1, 5)));
+    }
+
+    #[test]
+    fn snap_fraction_clamps() {
+        assert_eq!(snap_fraction((1, 1), MIN, MAX, STEP, false), Some((1, 5)));
+        assert_eq!(snap_fraction((1, 1), MIN, MAX, STEP, true), Some((1, 5)));
+        assert_eq!(snap_fraction((1, 60), MIN, MAX, STEP, false), Some(MIN));
+        // Rounding up past the end stops at max
+        assert_eq!(snap_fraction((7, 40), (1, 10), (1, 5), (1, 20), true), Some((1, 5)));
+        assert_eq!(snap_fraction((1, 30), MIN, MAX, (0, 1), false), Some((1, 30)));
+        assert_eq!(snap_fraction((1, 0), MIN, MAX, STEP, false), None);
+    }
+
+    #[test]
+    fn snap_fraction_largest_values() {
+        let max = u32::MAX;
+        let snapped = snap_fraction((max, 1), (1, max), (max, 1), (1, max), false);
+        assert_eq!(snapped, Some((max, 1)));
+        let snapped = snap_fraction((max - 1, max), (1, max), (max, max - 1), (1, max), true);
+        assert_eq!(snapped, Some((max - 1, max)));
+    }
+
+    #[test]
+    fn stepwise_fraction_contains() {
+        let info = DisStepInfo::StepwiseFraction { min: MIN, max: MAX, step: STEP };
+        assert!(info.contains((1, 5)));
+        assert!(info.contains((333334, 10000000)));
+        assert!(!info.contains((1, 15)));
+        assert!(!info.contains((1, 1)));
+    }
+
+    #[test]
+    fn expand_fractions_stays_on_the_grid() {
+        let info = DisStepInfo::StepwiseFraction { min: MIN, max: MAX, step: STEP };
+        let fractions = expand_fractions(MIN, MAX, STEP, &[(1, 12)]);
+        assert!(fractions.contains(&MIN) && fractions.contains(&(1, 5)));
+        assert!(fractions.contains(&(1, 10)) && fractions.contains(&(833333, 10000000)));
+        for &fraction in &fractions {
+            assert!(info.contains(fraction), "{:?} is off the grid", fraction);
+        }
+    }
+
+    #[test]
+    fn expand_sizes_stays_on_the_grid() {
+        let info = DisStepInfo::Stepwise { min: (16, 16), max: (1000, 700), step: (8, 4) };
+        let sizes = expand_sizes((16, 16), (1000, 700), (8, 4), &[(123, 45)]);
+        assert!(sizes.contains(&(16, 16)) && sizes.contains(&(1000, 700)));
+        assert!(sizes.contains(&(640, 480)));
+        assert!(sizes.contains(&(120, 44)) && sizes.contains(&(128, 48)));
+        for &size in &sizes {
+            assert!(info.contains(size), "{:?} is off the grid", size);
+        }
+    }
+
+    #[test]
+    fn formats_without_priorities_rank_the_same() {
+        // Listed first, so the picker yields it last
+        let camera = MockCamera::new(vec![
+            caps(FourCc::YUYV, false, &[(1920, 1080)], &[(1, 30)]),
+            caps(FourCc::MJPG, true, &[(640, 480)], &[(1, 30)]),
+        ], Vec::new());
+        let reqs = Constraints {
+            formats: Some(Fmt::default()),
+            .. Constraints::default()
+        };
+        let best = search(&camera, reqs).unwrap().remove(0);
+        assert_eq!(best.format, FourCc::YUYV);
+
+        let reqs = Constraints {
+            formats: Some(Fmt {
+                priorities: Some(vec![FourCc::MJPG, FourCc::YUYV]),
+                .. Fmt::default()
+            }),
+            .. Constraints::default()
+        };
+        let best = search(&camera, reqs).unwrap().remove(0);
+        assert_eq!(best.format, FourCc::MJPG);
+    }
+
+    /// YUYV at three sizes and MJPG at two, both at 15 and 30 fps
+    fn webcam() -> MockCamera {
+        MockCamera::new(vec![
+            caps(FourCc::YUYV, false, &[(640, 480), (1280, 720), (320, 240)], &[(1, 15), (1, 30)]),
+            caps(FourCc::MJPG, true, &[(1280, 720), (1920, 1080)], &[(1, 30), (1, 15)]),
+        ], Vec::new())
+    }
+
+    fn with_res(res: Res) -> Constraints {
+        Constraints {
+            resolutions: Some(res),
+            .. Constraints::default()
+        }
+    }
+
+    fn with_speed(speed: Speed) -> Constraints {
+        Constraints {
+            speeds: Some(speed),
+            .. Constraints::default()
+        }
+    }
+
+    #[test]
+    fn search_discrete_table() {
+        let configs = search(&webcam(), Constraints::default()).unwrap();
+        assert_eq!(configs.len(), 10);
+        assert_eq!(configs[0].format, FourCc::MJPG);
+        assert_eq!(configs[0].resolution, (1920, 1080));
+        assert_eq!(configs[0].interval, (1, 30));
+
+        let reqs = with_res(Res {
+            dir: Dir::Lowest,
+            .. Res::default()
+        });
+        let configs = search(&webcam(), reqs).unwrap();
+        assert_eq!(configs[0].resolution, (320, 240));
+        assert_eq!(configs[0].interval, (1, 30));
+
+        let reqs = with_speed(Speed {
+            dir: Dir::Lowest,
+            .. Speed::default()
+        });
+        assert_eq!(search(&webcam(), reqs).unwrap()[0].interval, (1, 15));
+    }
+
+    #[test]
+    fn search_stepwise_table() {
+        let mut stepwise = caps(FourCc::YUYV, false, &[(640, 480)], &[(1, 30)]);
+        stepwise.resolutions = DisStepInfo::Stepwise {
+            min: (32, 32),
+            max: (1600, 1200),
+            step: (16, 8),
+        };
+        stepwise.intervals = vec![((640, 480), DisStepInfo::StepwiseFraction {
+            min: (1, 60),
+            max: (1, 1),
+            step: (1, 60),
+        })];
+        let camera = MockCamera::new(vec![stepwise], Vec::new());
+
+        let best = search(&camera, Constraints::default()).unwrap().remove(0);
+        assert_eq!(best.resolution, (1600, 1200));
+        assert_eq!(best.interval, (1, 60));
+
+        let reqs = Constraints {
+            resolutions: Some(Res {
+                dir: Dir::Nearest,
+                target: Some((1000, 500)),
+                .. Res::default()
+            }),
+            speeds: Some(Speed {
+                dir: Dir::Nearest,
+                target_fps: Some(25.),
+                .. Speed::default()
+            }),
+            .. Constraints::default()
+        };
+        let best = search(&camera, reqs).unwrap().remove(0);
+        assert_eq!(best.resolution, (1008, 504));
+        // 1/25 is off the grid, 2/60 and 3/60 are either side of it
+        assert_eq!(best.interval, (1, 30));
+        for config in search(&camera, Constraints::default()).unwrap() {
+            assert!(camera.formats[0].resolutions.contains(config.resolution));
+        }
+    }
+
+    #[test]
+    fn search_limits() {
+        let reqs = with_res(Res {
+            min: Some((640, 480)),
+            max: Some((1280, 720)),
+            .. Res::default()
+        });
+        let configs = search(&webcam(), reqs).unwrap();
+        assert_eq!(configs.len(), 6);
+        assert!(configs.iter().all(|c| c.resolution == (640, 480) || c.resolution == (1280, 720)));
+
+        let reqs = with_speed(Speed {
+            max_fps: Some(20.),
+            .. Speed::default()
+        });
+        let configs = search(&webcam(), reqs).unwrap();
+        assert!(configs.iter().all(|c| c.interval == (1, 15)));
+    }
+
+    #[test]
+    fn report_says_what_was_rejected() {
+        let camera = MockCamera::new(vec![
+            caps(FourCc::YUYV, false, &[(640, 480)], &[(1, 30)]),
+            caps(FourCc::MJPG, true, &[(1280, 720)], &[(1, 30)]),
+        ], Vec::new());
+        let reqs = Constraints {
+            formats: Some(Fmt {
+                compress: Pref::Never,
+                .. Fmt::default()
+            }),
+            resolutions: Some(Res {
+                min: Some((800, 600)),
+                .. Res::default()
+            }),
+            .. Constraints::default()
+        };
+        let report = search(&camera, reqs).err().unwrap();
+        assert_eq!(report.rejected.len(), 2);
+        let by_res = &report.rejected[0];
+        assert_eq!(by_res.format, FourCc::YUYV);
+        assert_eq!((by_res.resolution, by_res.interval), (Some((640, 480)), None));
+        match by_res.reason {
+            Reason::Constraint(name) => assert_eq!(name, "Res.min"),
+            ref reason => panic!("{:?}", reason),
+        }
+        assert_eq!(report.rejected[1].format, FourCc::MJPG);
+        assert_eq!(report.to_string(), "no configuration satisfies the constraints:\n    \
+                                        YUYV 640x480: rejected by Res.min\n    \
+                                        MJPG: rejected by Fmt.compress");
+    }
+
+    #[test]
+    fn report_enumeration_failures() {
+        let mut broken = caps(FourCc::YUYV, false, &[(640, 480)], &[(1, 30)]);
+        broken.intervals.clear();
+        let report = search(&MockCamera::new(vec![broken], Vec::new()), Constraints::default())
+            .err().unwrap();
+        assert_eq!(report.rejected.len(), 1);
+        match report.rejected[0].reason {
+            Reason::Enumeration(_) => (),
+            ref reason => panic!("{:?}", reason),
+        }
+    }
+
+    #[test]
+    fn search_a_snapshot() {
+        let snapshot = Snapshot::of(&webcam()).unwrap();
+        let snapshot = Snapshot::from_json(&snapshot.to_json()).unwrap();
+        let camera = MockCamera::from(snapshot);
+        let configs = search(&camera, Constraints::default()).unwrap();
+        let expected = search(&webcam(), Constraints::default()).unwrap();
+        assert_eq!(configs.len(), expected.len());
+        for (config, expected) in configs.iter().zip(&expected) {
+            assert_eq!((config.format, config.resolution, config.interval),
+                       (expected.format, expected.resolution, expected.interval));
+        }
+    }
+
+    #[test]
+    fn start_a_mock() {
+        let mut camera = webcam();
+        camera.frames = vec![vec![1], vec![2]];
+        let config = search(&camera, Constraints::default()).unwrap().remove(0);
+        start(&mut camera, &config).unwrap();
+        assert_eq!(camera.started, Some((FourCc::MJPG, (1920, 1080), (1, 30))));
+        let frames: Vec<Vec<u8>> = (0..3).map(|_| camera.capture().unwrap().to_vec()).collect();
+        assert_eq!(frames, vec![vec![1], vec![2], vec![1]]);
+
+        let mut camera = webcam();
+        let config = ConfigSummary {
+            interval: (1, 60),
+            .. config
+        };
+        assert!(start(&mut camera, &config).is_err());
+        assert!(camera.capture().is_err());
+    }
+
+    #[test]
+    fn intervals_compare_by_fps() {
+        assert_eq!(cmp_fps(&(1, 30), &(1, 15)), Ordering::Greater);
+        assert_eq!(cmp_fps(&(2, 15), &(1, 10)), Ordering::Less);
+        assert_eq!(cmp_fps(&(1001, 30000), &(1, 30)), Ordering::Less);
+        // Equal rates only differ by the tie break
+        assert_eq!(cmp_fps(&(2, 60), &(1, 30)).reverse(), cmp_fps(&(1, 30), &(2, 60)));
+        assert_eq!(interval_of(25.), (1, 25));
+        assert_eq!(interval_of(29.97), (100, 2997));
+    }
+
+    #[test]
+    fn speed_limits_in_fps() {
+        let speed = Speed {
+            min_fps: Some(10.),
+            max_fps: Some(25.),
+            .. Speed::default()
+        };
+        assert_eq!(speed.violation((2, 15)), Some("Speed.min_fps"));
+        assert_eq!(speed.violation((1, 10)), None);
+        assert_eq!(speed.violation((1, 25)), None);
+        assert_eq!(speed.violation((1, 30)), Some("Speed.max_fps"));
+        // A zero interval is infinitely fast, a zero denominator never comes
+        assert_eq!(speed.violation((0, 1)), Some("Speed.max_fps"));
+        assert_eq!(speed.violation((1, 0)), Some("Speed.min_fps"));
+
+        let mut intervals = vec![(1, 30), (2, 15), (1, 10)];
+        intervals.sort_by(|a, b| Speed::default().order(a, b));
+        assert_eq!(intervals, vec![(2, 15), (1, 10), (1, 30)]);
+    }
+
+    #[test]
+    fn resolution_limits_per_dimension() {
+        let res = Res {
+            max: Some((640, 480)),
+            .. Res::default()
+        };
+        assert_eq!(res.violation((640, 2000)), Some("Res.max"));
+        assert_eq!(res.violation((639, 100)), None);
+        let res = Res {
+            min: Some((640, 480)),
+            .. Res::default()
+        };
+        assert_eq!(res.violation((639, 2000)), Some("Res.min"));
+        assert_eq!(res.violation((640, 480)), None);
+
+        let res = Res {
+            min_pixels: Some(300000),
+            max_pixels: Some(1000000),
+            .. Res::default()
+        };
+        assert_eq!(res.violation((320, 240)), Some("Res.min_pixels"));
+        assert_eq!(res.violation((640, 480)), None);
+        assert_eq!(res.violation((1280, 960)), Some("Res.max_pixels"));
+
+        let res = Res {
+            aspect: Some((16, 9)),
+            .. Res::default()
+        };
+        assert_eq!(res.violation((1366, 768)), None);
+        assert_eq!(res.violation((1280, 720)), None);
+        assert_eq!(res.violation((640, 480)), Some("Res.aspect"));
+
+        let mut sizes = vec![(1280, 720), (640, 2000), (1000, 900)];
+        sizes.sort_by(|a, b| Res::default().order(a, b));
+        assert_eq!(sizes, vec![(1000, 900), (1280, 720), (640, 2000)]);
+    }
+
+    #[test]
+    fn nearest_distance() {
+        let res = Res {
+            dir: Dir::Nearest,
+            target: Some((1280, 720)),
+            .. Res::default()
+        };
+        assert_eq!(res.distance((1280, 720)), Some(0.));
+        let half = res.distance((640, 360)).unwrap();
+        let double = res.distance((2560, 1440)).unwrap();
+        assert!((half - double).abs() < 1e-9);
+        // On a tie the bigger one is more wanted
+        assert_eq!(res.order(&(640, 360), &(2560, 1440)), Ordering::Less);
+        assert_eq!(res.order(&(1280, 720), &(1920, 1080)), Ordering::Greater);
+        assert_eq!(Res::default().distance((1280, 720)), None);
+
+        let speed = Speed {
+            dir: Dir::Nearest,
+            target_fps: Some(25.),
+            .. Speed::default()
+        };
+        assert_eq!(speed.distance((1, 25)), Some(0.));
+        assert_eq!(speed.order(&(1, 30), &(1, 15)), Ordering::Greater);
+    }
+
+    #[test]
+    fn search_nearest() {
+        let reqs = Constraints {
+            resolutions: Some(Res {
+                dir: Dir::Nearest,
+                target: Some((1000, 600)),
+                .. Res::default()
+            }),
+            speeds: Some(Speed {
+                dir: Dir::Nearest,
+                target_fps: Some(12.),
+                .. Speed::default()
+            }),
+            .. Constraints::default()
+        };
+        let best = search(&webcam(), reqs).unwrap().remove(0);
+        assert_eq!((best.resolution, best.interval), ((1280, 720), (1, 15)));
+
+        // Without a target it means highest
+        let reqs = with_res(Res {
+            dir: Dir::Nearest,
+            .. Res::default()
+        });
+        assert_eq!(search(&webcam(), reqs).unwrap()[0].resolution, (1920, 1080));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::slice;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rscam::{Camera, Config, FormatInfo};
+use rscam::Error as V4l2Error;
+
+use controls::{ControlInfo, ControlValue};
+use ffi;
+use ffi::{Region, V4l2Buffer};
+use fourcc::FourCc;
+use frame::Frame;
+use source::{Buffer, CaptureSource};
+use DisStepInfo;
+use V4l2Result;
+
+/// The descriptor streaming goes through and the buffers mapped from it,
+/// kept alive by the camera and every frame still out
+struct Mapping {
+    file: File,
+    regions: Vec<Region>,
+    format: FourCc,
+    resolution: (u32, u32),
+}
+
+// The regions are only read, and only while the driver has handed them over
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+/// A buffer dequeued from an `MmapCamera`, queued back once dropped
+pub struct DriverBuffer {
+    mapping: Arc<Mapping>,
+    buffer: V4l2Buffer,
+}
+
+impl Deref for DriverBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let region = &self.mapping.regions[self.buffer.index as usize];
+        let len = (self.buffer.bytesused as usize).min(region.len);
+        unsafe { slice::from_raw_parts(region.ptr, len) }
+    }
+}
+
+impl Drop for DriverBuffer {
+    fn drop(&mut self) {
+        // Fails once streaming has stopped, when there's nothing to queue for
+        let _ = ffi::xioctl(&self.mapping.file, ffi::VIDIOC_QBUF, &mut self.buffer);
+    }
+}
+
+/// A V4L2 device that streams through buffers of its own, so frames keep
+/// the kernel's timestamp, sequence number and flags, which rscam drops.
+/// Everything else goes through an rscam `Camera` on the same device.
+///
+/// It talks to the driver directly, without libv4l2, so the formats
+/// libv4l2 emulates aren't on offer.
+pub struct MmapCamera {
+    camera: Camera,
+    file: File,
+    mapping: Option<Arc<Mapping>>,
+}
+
+impl MmapCamera {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let name = try!(path.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path is not UTF-8")
+        }));
+        Ok(MmapCamera {
+            camera: try!(Camera::new(name)),
+            file: try!(OpenOptions::new().read(true).write(true).open(path)),
+            mapping: None,
+        })
+    }
+
+    pub fn get_ref(&self) -> &Camera {
+        &self.camera
+    }
+
+    /// Sets the format and interval, then maps and queues the buffers
+    fn tune(&self, config: &Config) -> V4l2Result<Mapping> {
+        if config.format.len() != 4 {
+            return Err(V4l2Error::BadFormat);
+        }
+        let format = [config.format[0], config.format[1], config.format[2], config.format[3]];
+        let pix = try!(ffi::s_fmt(&self.file, config.resolution, format, config.field));
+        if (pix.width, pix.height) != config.resolution {
+            return Err(V4l2Error::BadResolution);
+        }
+        if pix.pixelformat != format {
+            return Err(V4l2Error::BadFormat);
+        }
+        if pix.field != config.field {
+            return Err(V4l2Error::BadField);
+        }
+        let picked = try!(ffi::s_parm(&self.file, config.interval));
+        if !close_enough(picked, config.interval) {
+            return Err(V4l2Error::BadInterval);
+        }
+
+        let count = try!(ffi::reqbufs(&self.file, config.nbuffers));
+        let file = try!(self.file.try_clone());
+        let mut regions = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let mut buffer = V4l2Buffer::mmap(index);
+            try!(ffi::xioctl(&file, ffi::VIDIOC_QUERYBUF, &mut buffer));
+            regions.push(try!(Region::map(&file, buffer.length as usize, buffer.offset)));
+        }
+        for index in 0..count {
+            try!(ffi::xioctl(&file, ffi::VIDIOC_QBUF, &mut V4l2Buffer::mmap(index)));
+        }
+        try!(ffi::stream(&file, true));
+        Ok(Mapping {
+            file: file,
+            regions: regions,
+            format: FourCc(format),
+            resolution: config.resolution,
+        })
+    }
+}
+
+/// Whether the interval the driver `picked` is within 1% of the one `asked`
+/// for. Drivers round to their own units, like 333333/10000000 for 1/30.
+fn close_enough(picked: (u32, u32), asked: (u32, u32)) -> bool {
+    if picked.0 == 0 || picked.1 == 0 {
+        return false;
+    }
+    let (picked_num, picked_den) = (picked.0 as u128, picked.1 as u128);
+    let (asked_num, asked_den) = (asked.0 as u128, asked.1 as u128);
+    let (a, b) = (picked_num * asked_den, asked_num * picked_den);
+    a.abs_diff(b) * 100 <= b
+}
+
+impl Drop for MmapCamera {
+    fn drop(&mut self) {
+        if self.mapping.is_some() {
+            let _ = ffi::stream(&self.file, false);
+        }
+    }
+}
+
+impl CaptureSource for MmapCamera {
+    /// The formats the driver has itself, leaving out those libv4l2 emulates
+    fn formats(&self) -> Vec<FormatInfo> {
+        let formats = CaptureSource::formats(&self.camera);
+        formats.into_iter().filter(|format| !format.emulated).collect()
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        CaptureSource::resolutions(&self.camera, format)
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        CaptureSource::intervals(&self.camera, format, resolution)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        let mapping = try!(self.tune(config));
+        self.mapping = Some(Arc::new(mapping));
+        Ok(())
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        let mapping = match self.mapping {
+            Some(ref mapping) => mapping.clone(),
+            None => return Err(io::Error::other("not streaming")),
+        };
+        let mut buffer = V4l2Buffer::mmap(0);
+        try!(ffi::xioctl(&mapping.file, ffi::VIDIOC_DQBUF, &mut buffer));
+        let clock = buffer.flags & ffi::BUF_FLAG_TIMESTAMP_MASK;
+        let timestamp = if clock == ffi::BUF_FLAG_TIMESTAMP_MONOTONIC {
+            let stamp = &buffer.timestamp;
+            Some(Duration::new(stamp.tv_sec as u64, stamp.tv_usec as u32 * 1000))
+        } else {
+            None
+        };
+        let (sequence, flags) = (buffer.sequence, buffer.flags);
+        let (format, resolution) = (mapping.format, mapping.resolution);
+        let data = Buffer::Dequeued(DriverBuffer {
+            mapping: mapping,
+            buffer: buffer,
+        });
+        let mut frame = Frame::new(data, format, resolution);
+        frame.timestamp = timestamp;
+        frame.sequence = Some(sequence);
+        frame.flags = flags;
+        Ok(frame)
+    }
+
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        CaptureSource::controls(&self.camera)
+    }
+
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        CaptureSource::control(&self.camera, id)
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        self.camera.write_control(id, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::close_enough;
+
+    #[test]
+    fn intervals_the_driver_rounded_are_close_enough() {
+        assert!(close_enough((1, 30), (1, 30)));
+        assert!(close_enough((2, 60), (1, 30)));
+        // 100ns units, which overflow a u32 when cross-multiplied
+        assert!(close_enough((333333, 10000000), (1, 30)));
+        assert!(close_enough((1, 30), (333333, 10000000)));
+        assert!(close_enough((1001, 30000), (1, 30)));
+        assert!(!close_enough((1, 15), (1, 30)));
+        assert!(!close_enough((1, 25), (1, 30)));
+        assert!(!close_enough((0, 1), (1, 30)));
+        assert!(!close_enough((1, 0), (1, 30)));
+    }
+}
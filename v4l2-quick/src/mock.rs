@@ -0,0 +1,189 @@
+use std::io;
+
+use rscam::{Config, FormatInfo};
+use rscam::Error as V4l2Error;
+use rscam::consts::FLAG_INACTIVE;
+#[cfg(test)]
+use rscam::consts::{CID_BRIGHTNESS, CID_EXPOSURE_ABSOLUTE, CID_EXPOSURE_AUTO};
+#[cfg(test)]
+use rscam::consts::CID_POWER_LINE_FREQUENCY;
+
+use caps::FormatCaps;
+use controls::{ControlInfo, ControlValue};
+#[cfg(test)]
+use controls::ControlKind;
+use fourcc::FourCc;
+use frame::Frame;
+use source::{Buffer, CaptureSource, Mode};
+use DisStepInfo;
+#[cfg(test)]
+use ConfigSummary;
+use V4l2Result;
+
+/// A `CaptureSource` with a scripted capability table and scripted frames,
+/// to exercise the picker and the server without a device
+#[derive(Clone, Debug, Default)]
+pub struct MockCamera {
+    pub formats: Vec<FormatCaps>,
+    /// Handed out by `capture` in order, starting over after the last one
+    pub frames: Vec<Vec<u8>>,
+    /// OS error codes `start` fails with, one per call, before it succeeds
+    pub start_errors: Vec<i32>,
+    /// The format, resolution and interval it was started with
+    pub started: Option<Mode>,
+    /// Written controls keep the value they were given
+    pub controls: Vec<ControlInfo>,
+    /// Pairs of control ids, writing the first makes the second writable,
+    /// like switching auto exposure to manual does for the exposure time
+    pub unlocks: Vec<(u32, u32)>,
+    captured: usize,
+}
+
+impl MockCamera {
+    pub fn new(formats: Vec<FormatCaps>, frames: Vec<Vec<u8>>) -> Self {
+        MockCamera {
+            formats: formats,
+            frames: frames,
+            .. Default::default()
+        }
+    }
+
+    fn find(&self, format: FourCc) -> V4l2Result<&FormatCaps> {
+        self.formats.iter()
+            .find(|caps| caps.format == format)
+            .ok_or(V4l2Error::BadFormat)
+    }
+}
+
+impl CaptureSource for MockCamera {
+    fn formats(&self) -> Vec<FormatInfo> {
+        self.formats.iter().map(FormatCaps::info).collect()
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        self.find(format).map(|caps| caps.resolutions.clone())
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        let caps = try!(self.find(format));
+        caps.intervals_at(resolution).cloned().ok_or(V4l2Error::BadResolution)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        if !self.start_errors.is_empty() {
+            let code = self.start_errors.remove(0);
+            return Err(V4l2Error::Io(io::Error::from_raw_os_error(code)));
+        }
+        if config.format.len() != 4 {
+            return Err(V4l2Error::BadFormat);
+        }
+        let format = FourCc([config.format[0], config.format[1],
+                             config.format[2], config.format[3]]);
+        let intervals = try!(self.intervals(format, config.resolution));
+        if !intervals.contains(config.interval) {
+            return Err(V4l2Error::BadInterval);
+        }
+        self.started = Some((format, config.resolution, config.interval));
+        Ok(())
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        let (format, resolution, _) = match self.started {
+            Some(started) => started,
+            None => return Err(io::Error::other("not streaming")),
+        };
+        if self.frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no frames scripted"));
+        }
+        let frame = self.frames[self.captured % self.frames.len()].clone();
+        self.captured += 1;
+        Ok(Frame::new(Buffer::Owned(frame), format, resolution))
+    }
+
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        Ok(self.controls.clone())
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        let control = try!(self.controls.iter_mut()
+            .find(|control| control.id == id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such control")));
+        if *value != ControlValue::Press {
+            control.value = Some(value.clone());
+        }
+        for &(_, unlocked) in self.unlocks.iter().filter(|&&(by, _)| by == id) {
+            if let Some(control) = self.controls.iter_mut().find(|control| control.id == unlocked) {
+                control.flags &= !FLAG_INACTIVE;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A format offering `intervals` at every one of `sizes`
+#[cfg(test)]
+pub fn caps(format: FourCc, compressed: bool, sizes: &[(u32, u32)], intervals: &[(u32, u32)])
+        -> FormatCaps {
+    FormatCaps {
+        format: format,
+        description: String::new(),
+        compressed: compressed,
+        emulated: false,
+        resolutions: DisStepInfo::Discretes(sizes.to_vec()),
+        intervals: sizes.iter()
+            .map(|&size| (size, DisStepInfo::Discretes(intervals.to_vec())))
+            .collect(),
+        failures: Vec::new(),
+    }
+}
+
+/// A progressive config with two buffers and no stride
+#[cfg(test)]
+pub fn summary(format: FourCc, resolution: (u32, u32), interval: (u32, u32)) -> ConfigSummary {
+    ConfigSummary {
+        interval: interval,
+        resolution: resolution,
+        format: format,
+        field: 0,
+        nbuffers: 2,
+        stride: None,
+    }
+}
+
+/// A writable control holding `value`
+#[cfg(test)]
+pub fn control(id: u32, name: &str, kind: ControlKind, value: Option<ControlValue>)
+        -> ControlInfo {
+    ControlInfo {
+        id: id,
+        name: name.to_string(),
+        kind: kind,
+        value: value,
+        flags: 0,
+    }
+}
+
+/// The controls of a typical webcam, with the exposure time inactive until
+/// auto exposure is switched to manual, and a gap in the power line menu
+#[cfg(test)]
+pub fn webcam_controls() -> Vec<ControlInfo> {
+    let menu = |items: &[(u32, &str)], default: u32| ControlKind::Menu {
+        items: items.iter().map(|&(index, name)| (index, name.to_string())).collect(),
+        default: default,
+    };
+    let mut exposure = control(CID_EXPOSURE_ABSOLUTE, "Exposure (Absolute)",
+                               ControlKind::Integer { min: 3, max: 2047, step: 1, default: 250 },
+                               Some(ControlValue::Integer(250)));
+    exposure.flags = FLAG_INACTIVE;
+    vec![
+        control(CID_BRIGHTNESS, "Brightness",
+                ControlKind::Integer { min: -64, max: 64, step: 4, default: 0 },
+                Some(ControlValue::Integer(0))),
+        control(CID_POWER_LINE_FREQUENCY, "Power Line Frequency",
+                menu(&[(0, "Disabled"), (2, "60 Hz")], 2), Some(ControlValue::Integer(2))),
+        control(CID_EXPOSURE_AUTO, "Exposure, Auto",
+                menu(&[(1, "Manual Mode"), (3, "Aperture Priority Mode")], 3),
+                Some(ControlValue::Integer(3))),
+        exposure,
+    ]
+}
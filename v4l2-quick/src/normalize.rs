@@ -0,0 +1,244 @@
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::str::FromStr;
+
+use convert::{ConvertError, PixelLayout, RgbFormat};
+use fourcc::FourCc;
+
+/// The significant bits of the 16-bit greyscale formats, like those of
+/// thermal and depth cameras. Samples are little endian, in the low bits.
+pub fn sample_bits(format: FourCc) -> Option<u32> {
+    match format {
+        FourCc::Y10 => Some(10),
+        FourCc::Y12 => Some(12),
+        FourCc::Y14 => Some(14),
+        FourCc::Y16 | FourCc::Z16 => Some(16),
+        _ => None,
+    }
+}
+
+/// The samples of a 16-bit greyscale frame, rows without padding
+pub fn samples(data: &[u8], layout: &PixelLayout) -> Result<Vec<u16>, ConvertError> {
+    let format = layout.format;
+    if sample_bits(format).is_none() {
+        return Err(ConvertError::Unsupported(format));
+    }
+    let (width, height) = (layout.resolution.0 as usize, layout.resolution.1 as usize);
+    let row = width * 2;
+    let stride = layout.stride.unwrap_or(row);
+    if stride < row {
+        return Err(ConvertError::BadStride { stride: stride, min: row });
+    }
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+    let needed = stride * (height - 1) + row;
+    if data.len() < needed {
+        return Err(ConvertError::TooShort { len: data.len(), needed: needed });
+    }
+    let mut samples = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let line = &data[y * stride..y * stride + row];
+        samples.extend(line.chunks(2).map(|pair| pair[0] as u16 | (pair[1] as u16) << 8));
+    }
+    Ok(samples)
+}
+
+/// The samples that map to the ends of the palette
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Range {
+    /// Fixed raw values, like the readings of two known temperatures.
+    /// Anything outside is clamped to the ends.
+    Fixed { min: u16, max: u16 },
+    /// The coldest and hottest samples of each frame
+    Auto,
+}
+
+/// Colors for the normalized samples, from the lowest to the highest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    /// Black to white
+    Grey,
+    /// Black through blue, magenta and orange to white, the usual thermal look
+    Iron,
+    /// Blue through cyan, green and yellow to red
+    Rainbow,
+}
+
+impl Palette {
+    /// The colors spread evenly from the lowest to the highest
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match *self {
+            Palette::Grey => &[[0, 0, 0], [255, 255, 255]],
+            Palette::Iron => &[
+                [0, 0, 0], [32, 0, 140], [180, 0, 160], [255, 110, 0], [255, 220, 40],
+                [255, 255, 255],
+            ],
+            Palette::Rainbow => &[
+                [0, 0, 255], [0, 255, 255], [0, 255, 0], [255, 255, 0], [255, 0, 0],
+            ],
+        }
+    }
+
+    /// The color of each of 256 levels
+    fn table(&self) -> Vec<[u8; 3]> {
+        let stops = self.stops();
+        let spans = (stops.len() - 1) as f32;
+        (0..256).map(|level| {
+            let at = level as f32 / 255. * spans;
+            let index = (at as usize).min(stops.len() - 2);
+            let part = at - index as f32;
+            let (from, to) = (stops[index], stops[index + 1]);
+            let mut color = [0; 3];
+            for channel in 0..3 {
+                let (from, to) = (from[channel] as f32, to[channel] as f32);
+                color[channel] = (from + (to - from) * part).round() as u8;
+            }
+            color
+        }).collect()
+    }
+}
+
+/// A name that isn't a palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsePaletteError(String);
+
+impl Display for ParsePaletteError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "no palette called \"{}\", there are grey, iron and rainbow", self.0)
+    }
+}
+
+impl StdError for ParsePaletteError {}
+
+impl FromStr for Palette {
+    type Err = ParsePaletteError;
+
+    fn from_str(name: &str) -> Result<Self, ParsePaletteError> {
+        match &name.to_ascii_lowercase()[..] {
+            "grey" | "gray" => Ok(Palette::Grey),
+            "iron" | "ironbow" => Ok(Palette::Iron),
+            "rainbow" => Ok(Palette::Rainbow),
+            _ => Err(ParsePaletteError(name.to_string())),
+        }
+    }
+}
+
+/// How 16-bit samples are brought down to colors that can be shown
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Normalize {
+    pub range: Range,
+    pub palette: Palette,
+}
+
+impl Default for Normalize {
+    fn default() -> Self {
+        Normalize {
+            range: Range::Auto,
+            palette: Palette::Grey,
+        }
+    }
+}
+
+impl Normalize {
+    /// Maps a 16-bit greyscale frame laid out as `layout` onto the palette,
+    /// as packed RGB or RGBA
+    pub fn to_rgb(&self, data: &[u8], layout: &PixelLayout, out: RgbFormat)
+            -> Result<Vec<u8>, ConvertError> {
+        let samples = try!(samples(data, layout));
+        let (low, high) = match self.range {
+            Range::Fixed{min, max} => (min, max),
+            Range::Auto => samples.iter().fold((u16::MAX, 0), |(low, high), &sample| {
+                (low.min(sample), high.max(sample))
+            }),
+        };
+        // A flat frame, or a range upside down, all maps to the lowest color
+        let span = if high > low { (high - low) as f32 } else { f32::INFINITY };
+        let table = self.palette.table();
+        let mut rgb = Vec::with_capacity(samples.len() * out.bytes_per_pixel());
+        for sample in samples {
+            let level = (sample.max(low) - low) as f32 / span * 255.;
+            rgb.extend_from_slice(&table[level.round().min(255.) as usize]);
+            if out == RgbFormat::Rgba8 {
+                rgb.push(0xFF);
+            }
+        }
+        Ok(rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml;
+
+    /// Little endian samples, as a 16-bit greyscale frame has them
+    fn frame(samples: &[u16]) -> Vec<u8> {
+        samples.iter().flat_map(|&sample| vec![sample as u8, (sample >> 8) as u8]).collect()
+    }
+
+    fn grey(levels: &[u8]) -> Vec<u8> {
+        levels.iter().flat_map(|&level| vec![level; 3]).collect()
+    }
+
+    #[test]
+    fn reads_samples() {
+        assert_eq!(sample_bits(FourCc::Y12), Some(12));
+        assert_eq!(sample_bits(FourCc::GREY), None);
+        let layout = PixelLayout::packed(FourCc::Y16, (2, 1));
+        assert_eq!(samples(&[0x34, 0x12, 0xFF, 0xFF], &layout), Ok(vec![0x1234, 0xFFFF]));
+        // Padding between rows is skipped
+        let layout = PixelLayout { stride: Some(3), ..PixelLayout::packed(FourCc::Z16, (1, 2)) };
+        assert_eq!(samples(&[1, 0, 0xEE, 2, 0], &layout), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn sample_errors() {
+        let layout = PixelLayout::packed(FourCc::GREY, (1, 1));
+        assert_eq!(samples(&[0; 2], &layout), Err(ConvertError::Unsupported(FourCc::GREY)));
+        let layout = PixelLayout { stride: Some(3), ..PixelLayout::packed(FourCc::Y16, (2, 1)) };
+        assert_eq!(samples(&[0; 4], &layout), Err(ConvertError::BadStride { stride: 3, min: 4 }));
+        let layout = PixelLayout::packed(FourCc::Y16, (2, 1));
+        assert_eq!(samples(&[0; 3], &layout), Err(ConvertError::TooShort { len: 3, needed: 4 }));
+    }
+
+    #[test]
+    fn ranges() {
+        let layout = PixelLayout::packed(FourCc::Y16, (3, 1));
+        let auto = Normalize::default();
+        let rgb = auto.to_rgb(&frame(&[100, 200, 300]), &layout, RgbFormat::Rgb8);
+        assert_eq!(rgb, Ok(grey(&[0, 128, 255])));
+        // Fixed ranges clamp what falls outside them
+        let fixed = Normalize { range: Range::Fixed { min: 1000, max: 2000 }, ..auto };
+        let rgb = fixed.to_rgb(&frame(&[0, 1500, 5000]), &layout, RgbFormat::Rgb8);
+        assert_eq!(rgb, Ok(grey(&[0, 128, 255])));
+        let rgba = auto.to_rgb(&frame(&[7, 7, 7]), &layout, RgbFormat::Rgba8).unwrap();
+        assert_eq!(rgba, [0, 0, 0, 0xFF].repeat(3));
+    }
+
+    #[test]
+    fn palettes() {
+        for &palette in &[Palette::Grey, Palette::Iron, Palette::Rainbow] {
+            let (stops, table) = (palette.stops(), palette.table());
+            assert_eq!(table.len(), 256);
+            assert_eq!(table[0], stops[0]);
+            assert_eq!(table[255], stops[stops.len() - 1]);
+        }
+        assert_eq!("Gray".parse(), Ok(Palette::Grey));
+        assert_eq!("IRONBOW".parse(), Ok(Palette::Iron));
+        let err = "jet".parse::<Palette>().unwrap_err();
+        assert_eq!(err.to_string(), "no palette called \"jet\", there are grey, iron and rainbow");
+    }
+
+    #[test]
+    fn reads_settings() {
+        let normalize: Normalize = toml::from_str(
+            "palette = \"iron\"\n[range.fixed]\nmin = 1000\nmax = 2000\n").unwrap();
+        assert_eq!(normalize.range, Range::Fixed { min: 1000, max: 2000 });
+        assert_eq!(normalize.palette, Palette::Iron);
+    }
+}
@@ -0,0 +1,260 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rscam::{Config, FormatInfo};
+use rscam::Error as V4l2Error;
+
+use ffi;
+use fourcc::FourCc;
+use frame::Frame;
+use jpeg;
+use source::{Buffer, CaptureSource, Mode};
+use DisStepInfo;
+use V4l2Result;
+
+/// 3x5 glyphs for the frame counter, one row per byte, high bit on the left
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+type Rgb = [u8; 3];
+
+const BLACK: Rgb = [0, 0, 0];
+const WHITE: Rgb = [255, 255, 255];
+
+/// The 75% bars along the top, from the left
+const BARS: [Rgb; 7] = [
+    [191, 191, 191], [191, 191, 0], [0, 191, 191], [0, 191, 0],
+    [191, 0, 191], [191, 0, 0], [0, 0, 191],
+];
+
+/// The reversed blue bars under them
+const CASTELLATIONS: [Rgb; 7] = [
+    [0, 0, 191], [19, 19, 19], [191, 0, 191], [19, 19, 19],
+    [0, 191, 191], [19, 19, 19], [191, 191, 191],
+];
+
+/// -I, white, +Q and black, then the PLUGE steps, across the bottom.
+/// Widths are in 84ths of the frame, a top bar being 12.
+const PLUGE: [(Rgb, usize); 8] = [
+    ([0, 33, 76], 15), ([255, 255, 255], 15), ([50, 0, 106], 15), ([19, 19, 19], 15),
+    ([9, 9, 9], 4), ([19, 19, 19], 4), ([29, 29, 29], 4), ([19, 19, 19], 12),
+];
+
+/// A `CaptureSource` drawing SMPTE colour bars with a moving box and a
+/// frame counter, in MJPG or YUYV at any resolution and interval.
+/// For working on the client without a webcam.
+pub struct TestPattern {
+    /// JPEG quality of the MJPG frames, from 1 to 100
+    pub quality: u8,
+    config: Option<Mode>,
+    sequence: u64,
+    due: Option<Instant>,
+}
+
+impl TestPattern {
+    pub fn new() -> Self {
+        TestPattern {
+            quality: 80,
+            config: None,
+            sequence: 0,
+            due: None,
+        }
+    }
+
+    /// Draws frame number `sequence` as packed RGB
+    pub fn draw(&self, (width, height): (u32, u32), sequence: u64) -> Vec<u8> {
+        let (w, h) = (width as usize, height as usize);
+        let mut rgb = vec![0; w * h * 3];
+
+        let bars_end = h * 2 / 3;
+        let castellations_end = h * 3 / 4;
+        for y in 0..h {
+            for x in 0..w {
+                let color = if y < bars_end {
+                    BARS[x * 7 / w]
+                } else if y < castellations_end {
+                    CASTELLATIONS[x * 7 / w]
+                } else {
+                    pluge_at(x * 84 / w)
+                };
+                rgb[(y * w + x) * 3..(y * w + x + 1) * 3].copy_from_slice(&color);
+            }
+        }
+
+        // Bounce a box back and forth across the bars
+        let side = ::std::cmp::max(h / 6, 2);
+        if side < w && side < bars_end {
+            let travel = (w - side) as u64;
+            let step = ::std::cmp::max(w as u64 / 120, 1);
+            let offset = sequence * step % (travel * 2);
+            let x = if offset < travel { offset } else { travel * 2 - offset } as usize;
+            fill(&mut rgb, w, (x, (bars_end - side) / 2), (side, side), WHITE);
+        }
+
+        // The counter goes top left, on black so it stays legible
+        let digits = sequence.to_string();
+        let scale = ::std::cmp::max(h / 60, 1);
+        let (text_w, text_h) = ((digits.len() * 4 + 1) * scale, 7 * scale);
+        if text_w <= w && text_h <= h {
+            fill(&mut rgb, w, (0, 0), (text_w, text_h), BLACK);
+            for (i, digit) in digits.bytes().enumerate() {
+                let glyph = &DIGITS[(digit - b'0') as usize];
+                for (row, bits) in glyph.iter().enumerate() {
+                    for col in 0..3 {
+                        if bits & (0b100 >> col) != 0 {
+                            let origin = ((i * 4 + 1 + col) * scale, (row + 1) * scale);
+                            fill(&mut rgb, w, origin, (scale, scale), WHITE);
+                        }
+                    }
+                }
+            }
+        }
+        rgb
+    }
+}
+
+impl Default for TestPattern {
+    fn default() -> Self {
+        TestPattern::new()
+    }
+}
+
+fn pluge_at(slot: usize) -> Rgb {
+    let mut end = 0;
+    for &(color, width) in &PLUGE {
+        end += width;
+        if slot < end {
+            return color;
+        }
+    }
+    BLACK
+}
+
+fn fill(rgb: &mut [u8], width: usize, (x, y): (usize, usize), (w, h): (usize, usize), color: Rgb) {
+    for row in y..y + h {
+        for col in x..x + w {
+            let at = (row * width + col) * 3;
+            rgb[at..at + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Packs RGB into YUYV with BT.601 studio swing, sharing chroma across pairs
+fn to_yuyv(rgb: &[u8]) -> Vec<u8> {
+    let luma = |p: &[u8]| {
+        16. + (65.481 * p[0] as f32 + 128.553 * p[1] as f32 + 24.966 * p[2] as f32) / 255.
+    };
+    let mut out = Vec::with_capacity(rgb.len() / 3 * 2);
+    for pair in rgb.chunks(6) {
+        let (a, b) = (&pair[..3], &pair[3..]);
+        let avg = |i: usize| (a[i] as f32 + b[i] as f32) / 2.;
+        let (r, g, bl) = (avg(0), avg(1), avg(2));
+        let u = 128. + (-37.797 * r - 74.203 * g + 112. * bl) / 255.;
+        let v = 128. + (112. * r - 93.786 * g - 18.214 * bl) / 255.;
+        out.push(luma(a).round() as u8);
+        out.push(u.round() as u8);
+        out.push(luma(b).round() as u8);
+        out.push(v.round() as u8);
+    }
+    out
+}
+
+impl CaptureSource for TestPattern {
+    fn formats(&self) -> Vec<FormatInfo> {
+        vec![
+            FormatInfo {
+                format: FourCc::MJPG.0,
+                description: "Motion-JPEG test pattern".to_string(),
+                compressed: true,
+                emulated: false,
+            },
+            FormatInfo {
+                format: FourCc::YUYV.0,
+                description: "YUYV 4:2:2 test pattern".to_string(),
+                compressed: false,
+                emulated: false,
+            },
+        ]
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        if format != FourCc::MJPG && format != FourCc::YUYV {
+            return Err(V4l2Error::BadFormat);
+        }
+        // YUYV shares chroma between pixel pairs, so widths stay even
+        Ok(DisStepInfo::Stepwise {
+            min: (16, 16),
+            max: (1920, 1080),
+            step: (2, 1),
+        })
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        if !try!(self.resolutions(format)).contains(resolution) {
+            return Err(V4l2Error::BadResolution);
+        }
+        Ok(DisStepInfo::StepwiseFraction {
+            min: (1, 120),
+            max: (1, 1),
+            step: (0, 1),
+        })
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        if config.format.len() != 4 {
+            return Err(V4l2Error::BadFormat);
+        }
+        let format = FourCc([config.format[0], config.format[1],
+                             config.format[2], config.format[3]]);
+        let intervals = try!(self.intervals(format, config.resolution));
+        if !intervals.contains(config.interval) {
+            return Err(V4l2Error::BadInterval);
+        }
+        self.config = Some((format, config.resolution, config.interval));
+        self.sequence = 0;
+        self.due = None;
+        Ok(())
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        let (format, resolution, (num, den)) = match self.config {
+            Some(config) => config,
+            None => return Err(io::Error::other("not streaming")),
+        };
+
+        // Hold frames back to the interval, like a real device would
+        let now = Instant::now();
+        let due = self.due.unwrap_or(now);
+        if due > now {
+            thread::sleep(due - now);
+        }
+        let period = Duration::from_nanos(num as u64 * 1_000_000_000 / den as u64);
+        self.due = Some(::std::cmp::max(due, now) + period);
+        // Stamped as it is taken, the way a driver would
+        let timestamp = ffi::monotonic();
+
+        let sequence = self.sequence;
+        let rgb = self.draw(resolution, sequence);
+        self.sequence += 1;
+        let data = if format == FourCc::MJPG {
+            try!(jpeg::encode_rgb(&rgb, resolution, self.quality))
+        } else {
+            to_yuyv(&rgb)
+        };
+        let mut frame = Frame::new(Buffer::Owned(data), format, resolution);
+        frame.timestamp = Some(timestamp);
+        frame.sequence = Some(sequence as u32);
+        Ok(frame)
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+
+use controls::{BadValue, ControlInfo, ControlKind, ControlValue};
+use error::Error;
+use serial::SettingsFile;
+use source::CaptureSource;
+
+/// A value in a preset. Menus are set by the name of the entry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Setting {
+    Integer(i64),
+    Boolean(bool),
+    Entry(String),
+}
+
+impl Setting {
+    /// What to write to `control` for this setting
+    fn value_for(&self, control: &ControlInfo) -> Result<ControlValue, BadValue> {
+        match *self {
+            Setting::Integer(value) => Ok(ControlValue::Integer(value)),
+            Setting::Boolean(value) => Ok(ControlValue::Boolean(value)),
+            Setting::Entry(ref name) => match control.kind {
+                ControlKind::Menu{..} => {
+                    control.item(name).map(|index| ControlValue::Integer(index as i64))
+                        .ok_or(BadValue::NoSuchItem)
+                },
+                _ => Err(BadValue::WrongKind),
+            },
+        }
+    }
+}
+
+/// Control values by control name, like "Brightness" or "Exposure, Auto",
+/// applied in the order they are listed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preset {
+    pub settings: Vec<(String, Setting)>,
+}
+
+impl Preset {
+    /// The current value of every control of `source` that can be written
+    pub fn capture<S: CaptureSource + ?Sized>(source: &S) -> io::Result<Self> {
+        let mut settings = Vec::new();
+        for control in try!(source.controls()) {
+            if !control.is_writable() {
+                continue;
+            }
+            let setting = match (&control.kind, control.value) {
+                (ControlKind::Menu{items, ..}, Some(ControlValue::Integer(index))) => {
+                    items.iter()
+                        .find(|&&(item, _)| item as i64 == index)
+                        .map(|(_, name)| Setting::Entry(name.clone()))
+                },
+                (_, Some(ControlValue::Integer(value))) => Some(Setting::Integer(value)),
+                (_, Some(ControlValue::Boolean(value))) => Some(Setting::Boolean(value)),
+                _ => None,
+            };
+            if let Some(setting) = setting {
+                settings.push((control.name, setting));
+            }
+        }
+        Ok(Preset { settings: settings })
+    }
+
+    /// Writes every setting to `source`, carrying on past the ones that fail.
+    /// Returns the first failure.
+    pub fn apply<S: CaptureSource + ?Sized>(&self, source: &mut S) -> Result<(), Error> {
+        let mut pending: Vec<&(String, Setting)> = self.settings.iter().collect();
+        let mut failures = Vec::new();
+        // Turning an automatic mode off makes the controls it drives writable,
+        // so whatever wasn't writable gets a second go once the rest is done
+        for &retry in &[false, true] {
+            let controls = try!(source.controls());
+            let mut skipped = Vec::new();
+            for entry in pending {
+                let (ref name, ref setting) = *entry;
+                let control = match controls.iter().find(|c| c.name.eq_ignore_ascii_case(name)) {
+                    Some(control) => control,
+                    None => {
+                        let message = format!("no control called {}", name);
+                        failures.push(io::Error::new(io::ErrorKind::NotFound, message).into());
+                        continue;
+                    },
+                };
+                let checked = setting.value_for(control)
+                    .and_then(|value| control.check(&value).map(|_| value));
+                match checked {
+                    Ok(value) => if let Err(err) = source.write_control(control.id, &value) {
+                        failures.push(Error::from(err));
+                    },
+                    Err(BadValue::NotWritable) if !retry => skipped.push(entry),
+                    Err(problem) => failures.push(Error::Control(control.name.clone(), problem)),
+                }
+            }
+            pending = skipped;
+        }
+        failures.into_iter().next().map_or(Ok(()), Err)
+    }
+}
+
+impl Serialize for Preset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = try!(serializer.serialize_map(Some(self.settings.len())));
+        for (name, setting) in &self.settings {
+            try!(map.serialize_entry(name, setting));
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Preset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(PresetVisitor)
+    }
+}
+
+/// Reads a preset keeping the order of its settings, which a map would lose
+struct PresetVisitor;
+
+impl<'de> Visitor<'de> for PresetVisitor {
+    type Value = Preset;
+
+    fn expecting(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("a table of control names and values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Preset, A::Error> {
+        let mut settings = Vec::new();
+        while let Some(entry) = try!(access.next_entry()) {
+            settings.push(entry);
+        }
+        Ok(Preset { settings: settings })
+    }
+}
+
+/// Presets by name, like "daylight" and "night", kept in a TOML or JSON
+/// file with a table per preset
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Presets(pub BTreeMap<String, Preset>);
+
+impl Presets {
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.0.get(name)
+    }
+}
+
+impl SettingsFile for Presets {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{webcam_controls, MockCamera};
+    use rscam::consts::{CID_BRIGHTNESS, CID_EXPOSURE_ABSOLUTE, CID_EXPOSURE_AUTO};
+    use rscam::consts::CID_POWER_LINE_FREQUENCY;
+    use serde_json;
+    use toml;
+
+    fn camera() -> MockCamera {
+        let mut camera = MockCamera::default();
+        camera.controls = webcam_controls();
+        camera.unlocks = vec![(CID_EXPOSURE_AUTO, CID_EXPOSURE_ABSOLUTE)];
+        camera
+    }
+
+    fn value(camera: &MockCamera, id: u32) -> Option<ControlValue> {
+        camera.control(id).unwrap().value
+    }
+
+    fn preset(settings: &[(&str, Setting)]) -> Preset {
+        Preset {
+            settings: settings.iter().map(|&(name, ref setting)| {
+                (name.to_string(), setting.clone())
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn locked_controls_get_a_second_pass() {
+        // The exposure time comes before the mode that unlocks it
+        let night = preset(&[
+            ("Exposure (Absolute)", Setting::Integer(1000)),
+            ("exposure, auto", Setting::Entry("manual mode".to_string())),
+            ("Brightness", Setting::Integer(8)),
+        ]);
+        let mut unlocking = camera();
+        night.apply(&mut unlocking).unwrap();
+        assert_eq!(value(&unlocking, CID_EXPOSURE_ABSOLUTE), Some(ControlValue::Integer(1000)));
+        assert_eq!(value(&unlocking, CID_EXPOSURE_AUTO), Some(ControlValue::Integer(1)));
+        assert_eq!(value(&unlocking, CID_BRIGHTNESS), Some(ControlValue::Integer(8)));
+        // Still locked after the second pass, it fails but the rest is written
+        let mut locked = camera();
+        locked.unlocks.clear();
+        match night.apply(&mut locked) {
+            Err(Error::Control(ref name, BadValue::NotWritable)) => {
+                assert_eq!(name, "Exposure (Absolute)")
+            },
+            other => panic!("expected the exposure to stay locked, got {:?}", other),
+        }
+        assert_eq!(value(&locked, CID_EXPOSURE_ABSOLUTE), Some(ControlValue::Integer(250)));
+        assert_eq!(value(&locked, CID_BRIGHTNESS), Some(ControlValue::Integer(8)));
+    }
+
+    #[test]
+    fn apply_carries_on_and_returns_the_first_failure() {
+        let broken = preset(&[
+            ("Brightness", Setting::Integer(7)),
+            ("Contrast", Setting::Integer(10)),
+            ("Power Line Frequency", Setting::Entry("50 Hz".to_string())),
+            ("Brightness", Setting::Entry("Bright".to_string())),
+            ("Power Line Frequency", Setting::Entry("Disabled".to_string())),
+        ]);
+        let mut camera = camera();
+        match broken.apply(&mut camera) {
+            Err(Error::Control(ref name, BadValue::OutOfRange{..})) => {
+                assert_eq!(name, "Brightness")
+            },
+            other => panic!("expected the brightness to be out of range, got {:?}", other),
+        }
+        assert_eq!(value(&camera, CID_BRIGHTNESS), Some(ControlValue::Integer(0)));
+        assert_eq!(value(&camera, CID_POWER_LINE_FREQUENCY), Some(ControlValue::Integer(0)));
+        // A missing control is reported like any other failure
+        match preset(&[("Contrast", Setting::Integer(10))]).apply(&mut camera) {
+            Err(Error::V4l2(_)) => (),
+            other => panic!("expected no such control, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn settings_keep_the_order_they_were_written_in() {
+        let names = |preset: &Preset| -> Vec<String> {
+            preset.settings.iter().map(|(name, _)| name.clone()).collect()
+        };
+        let presets: Presets = toml::from_str(concat!(
+            "[night]\n",
+            "\"Exposure, Auto\" = \"Manual Mode\"\n",
+            "\"Exposure (Absolute)\" = 1000\n",
+            "Brightness = -8\n",
+            "\"White Balance, Auto\" = false\n",
+        )).unwrap();
+        let night = presets.get("night").unwrap();
+        assert_eq!(names(night), vec!["Exposure, Auto", "Exposure (Absolute)", "Brightness",
+                                      "White Balance, Auto"]);
+        assert_eq!(night.settings[0].1, Setting::Entry("Manual Mode".to_string()));
+        assert_eq!(night.settings[2].1, Setting::Integer(-8));
+        assert_eq!(night.settings[3].1, Setting::Boolean(false));
+        let json = r#"{"Zoom": 2, "Brightness": 8, "Contrast": 1}"#;
+        let preset: Preset = serde_json::from_str(json).unwrap();
+        assert_eq!(names(&preset), vec!["Zoom", "Brightness", "Contrast"]);
+        // And write back out in the same order
+        assert_eq!(serde_json::to_string(&preset).unwrap().replace(' ', ""),
+                   json.replace(' ', ""));
+    }
+
+    #[test]
+    fn capture_takes_what_could_be_written_back() {
+        let mut camera = camera();
+        let saved = Preset::capture(&camera).unwrap();
+        // The exposure time is inactive, menus are saved by name
+        assert_eq!(saved, preset(&[
+            ("Brightness", Setting::Integer(0)),
+            ("Power Line Frequency", Setting::Entry("60 Hz".to_string())),
+            ("Exposure, Auto", Setting::Entry("Aperture Priority Mode".to_string())),
+        ]));
+        preset(&[("Brightness", Setting::Integer(16))]).apply(&mut camera).unwrap();
+        saved.apply(&mut camera).unwrap();
+        assert_eq!(value(&camera, CID_BRIGHTNESS), Some(ControlValue::Integer(0)));
+    }
+}
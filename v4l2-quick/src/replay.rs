@@ -0,0 +1,322 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use rscam::{Config, FormatInfo};
+use rscam::Error as V4l2Error;
+
+use ffi;
+use fourcc::FourCc;
+use frame::Frame;
+use jpeg;
+use source::{Buffer, CaptureSource};
+use DisStepInfo;
+use V4l2Result;
+use interval_of;
+
+/// The rate of recordings that don't keep their timing
+const DEFAULT_INTERVAL: (u32, u32) = (1, 30);
+
+enum Frames {
+    /// A concatenated MJPEG recording, split into the ranges of each frame
+    Stream(Vec<u8>, Vec<(usize, usize)>),
+    /// A directory of JPEGs, read as they are played
+    Files(Vec<PathBuf>),
+}
+
+/// The frames of a recording, their timing if it kept any, and the first of them
+type Loaded = (Frames, Option<Vec<Duration>>, Option<Vec<u8>>);
+
+/// A `CaptureSource` playing back recorded MJPG footage, either a file of
+/// concatenated JPEGs or a directory of them played in name order
+pub struct Replay {
+    /// Play at this interval instead of the recorded timing
+    pub interval: Option<(u32, u32)>,
+    /// Start over after the last frame instead of failing with `UnexpectedEof`
+    pub looping: bool,
+    frames: Frames,
+    resolution: (u32, u32),
+    /// When each frame was taken, relative to the first, if the recording kept it
+    stamps: Option<Vec<Duration>>,
+    started: bool,
+    played: usize,
+    due: Option<Instant>,
+}
+
+impl Replay {
+    /// Loads the recording at `path`, a file or a directory
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (frames, stamps, first) = if try!(fs::metadata(path)).is_dir() {
+            try!(load_dir(path))
+        } else {
+            let mut data = Vec::new();
+            try!(File::open(path).and_then(|mut file| file.read_to_end(&mut data)));
+            let ranges = jpeg::split(&data);
+            let first = ranges.first().map(|&(start, end)| data[start..end].to_vec());
+            // A bare stream keeps no timing
+            (Frames::Stream(data, ranges), None, first)
+        };
+        let first = try!(first.ok_or_else(|| invalid("no JPEG frames in the recording")));
+        let resolution = try!(jpeg::dimensions(&first)
+            .ok_or_else(|| invalid("the first frame has no frame header")));
+        Ok(Replay {
+            interval: None,
+            looping: false,
+            frames: frames,
+            resolution: resolution,
+            stamps: stamps,
+            started: false,
+            played: 0,
+            due: None,
+        })
+    }
+
+    /// How many frames the recording holds
+    pub fn len(&self) -> usize {
+        match self.frames {
+            Frames::Stream(_, ref ranges) => ranges.len(),
+            Frames::Files(ref paths) => paths.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The interval frames are played at, on average for recorded timing
+    pub fn playback_interval(&self) -> (u32, u32) {
+        if let Some(interval) = self.interval {
+            return interval;
+        }
+        match self.stamps {
+            Some(ref stamps) if stamps.len() > 1 => {
+                let span = stamps[stamps.len() - 1];
+                let seconds = span.as_secs() as f32 + span.subsec_nanos() as f32 / 1e9;
+                interval_of((stamps.len() - 1) as f32 / seconds)
+            },
+            _ => DEFAULT_INTERVAL,
+        }
+    }
+
+    /// How long to hold frame `index` before showing the next one
+    fn delay(&self, index: usize) -> Duration {
+        if let (None, Some(stamps)) = (self.interval, self.stamps.as_ref()) {
+            if index + 1 < stamps.len() {
+                return stamps[index + 1] - stamps[index];
+            }
+        }
+        let (num, den) = self.playback_interval();
+        Duration::from_nanos(num as u64 * 1_000_000_000 / den as u64)
+    }
+
+    fn frame(&self, index: usize) -> io::Result<Vec<u8>> {
+        match self.frames {
+            Frames::Stream(ref data, ref ranges) => {
+                let (start, end) = ranges[index];
+                Ok(data[start..end].to_vec())
+            },
+            Frames::Files(ref paths) => {
+                let mut data = Vec::new();
+                try!(File::open(&paths[index]).and_then(|mut file| file.read_to_end(&mut data)));
+                Ok(data)
+            },
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Lists the JPEGs in `dir` in name order, with their timing taken from the
+/// modification times if those are increasing
+fn load_dir(dir: &Path) -> io::Result<Loaded> {
+    let mut paths = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let path = try!(entry).path();
+        let is_jpeg = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+        if is_jpeg {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut times = Vec::with_capacity(paths.len());
+    for path in &paths {
+        times.push(try!(fs::metadata(path).and_then(|meta| meta.modified())));
+    }
+    let stamps = recorded(&times);
+
+    let first = match paths.first() {
+        Some(path) => {
+            let mut data = Vec::new();
+            try!(File::open(path).and_then(|mut file| file.read_to_end(&mut data)));
+            Some(data)
+        },
+        None => None,
+    };
+    Ok((Frames::Files(paths), stamps, first))
+}
+
+/// The offsets of `times` from the first, unless they went backwards or all
+/// landed together, as they do when the files were copied
+fn recorded(times: &[SystemTime]) -> Option<Vec<Duration>> {
+    let first = match times.first() {
+        Some(&first) => first,
+        None => return None,
+    };
+    let mut stamps = Vec::with_capacity(times.len());
+    for time in times {
+        match time.duration_since(first) {
+            Ok(stamp) if stamps.last().is_none_or(|&last| stamp >= last) => stamps.push(stamp),
+            _ => return None,
+        }
+    }
+    if stamps.len() > 1 && stamps[stamps.len() - 1] > Duration::from_millis(0) {
+        Some(stamps)
+    } else {
+        None
+    }
+}
+
+impl CaptureSource for Replay {
+    fn formats(&self) -> Vec<FormatInfo> {
+        vec![FormatInfo {
+            format: FourCc::MJPG.0,
+            description: "Motion-JPEG recording".to_string(),
+            compressed: true,
+            emulated: false,
+        }]
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        if format != FourCc::MJPG {
+            return Err(V4l2Error::BadFormat);
+        }
+        Ok(DisStepInfo::Discretes(vec![self.resolution]))
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        if !try!(self.resolutions(format)).contains(resolution) {
+            return Err(V4l2Error::BadResolution);
+        }
+        Ok(DisStepInfo::Discretes(vec![self.playback_interval()]))
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        if config.format != &FourCc::MJPG.0[..] {
+            return Err(V4l2Error::BadFormat);
+        }
+        let intervals = try!(self.intervals(FourCc::MJPG, config.resolution));
+        if !intervals.contains(config.interval) {
+            return Err(V4l2Error::BadInterval);
+        }
+        self.started = true;
+        self.played = 0;
+        self.due = None;
+        Ok(())
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        if !self.started {
+            return Err(io::Error::other("not streaming"));
+        }
+        let index = if self.looping { self.played % self.len() } else { self.played };
+        if index >= self.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "end of the recording"));
+        }
+
+        let now = Instant::now();
+        let due = self.due.unwrap_or(now);
+        if due > now {
+            thread::sleep(due - now);
+        }
+        self.due = Some(::std::cmp::max(due, now) + self.delay(index));
+        let timestamp = ffi::monotonic();
+
+        let data = try!(self.frame(index));
+        let mut frame = Frame::new(Buffer::Owned(data), FourCc::MJPG, self.resolution);
+        frame.timestamp = Some(timestamp);
+        frame.sequence = Some(self.played as u32);
+        self.played += 1;
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::summary;
+    use start;
+
+    fn encoded(resolution: (u32, u32)) -> Vec<u8> {
+        let pixels = (resolution.0 * resolution.1 * 3) as usize;
+        jpeg::encode_rgb(&vec![128; pixels], resolution, 80).unwrap()
+    }
+
+    /// A recording of a 16x8 frame then an 8x8 one, played at 1000 fps
+    fn replay(name: &str) -> Replay {
+        let mut data = encoded((16, 8));
+        data.extend(encoded((8, 8)));
+        let name = format!("v4l2-quick-{}-{}.mjpg", name, ::std::process::id());
+        let path = ::std::env::temp_dir().join(name);
+        fs::write(&path, data).unwrap();
+        let mut replay = Replay::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        replay.interval = Some((1, 1000));
+        replay
+    }
+
+    #[test]
+    fn plays_a_recording_to_the_end() {
+        let mut replay = replay("end");
+        assert_eq!(replay.len(), 2);
+        // The first frame sets the size it claims to have
+        assert!(replay.resolutions(FourCc::MJPG).unwrap().contains((16, 8)));
+        assert!(!replay.resolutions(FourCc::MJPG).unwrap().contains((8, 8)));
+        assert!(replay.capture().is_err());
+        start(&mut replay, &summary(FourCc::MJPG, (16, 8), (1, 1000))).unwrap();
+        let first = replay.capture().unwrap();
+        assert_eq!((first.format, first.sequence), (FourCc::MJPG, Some(0)));
+        assert_eq!(jpeg::dimensions(&first), Some((16, 8)));
+        let second = replay.capture().unwrap();
+        assert_eq!((jpeg::dimensions(&second), second.sequence), (Some((8, 8)), Some(1)));
+        assert!(second.timestamp > first.timestamp);
+        let end = replay.capture().err().unwrap();
+        assert_eq!(end.kind(), io::ErrorKind::UnexpectedEof);
+        // Starting again rewinds
+        start(&mut replay, &summary(FourCc::MJPG, (16, 8), (1, 1000))).unwrap();
+        assert_eq!(replay.capture().unwrap().sequence, Some(0));
+    }
+
+    #[test]
+    fn loops_back_to_the_first_frame() {
+        let mut replay = replay("loop");
+        replay.looping = true;
+        start(&mut replay, &summary(FourCc::MJPG, (16, 8), (1, 1000))).unwrap();
+        let sizes: Vec<_> = (0..5)
+            .map(|_| jpeg::dimensions(&replay.capture().unwrap()).unwrap())
+            .collect();
+        assert_eq!(sizes, vec![(16, 8), (8, 8), (16, 8), (8, 8), (16, 8)]);
+        // It only offers its own format and rate
+        assert!(start(&mut replay, &summary(FourCc::YUYV, (16, 8), (1, 1000))).is_err());
+        assert!(start(&mut replay, &summary(FourCc::MJPG, (16, 8), (1, 30))).is_err());
+    }
+
+    #[test]
+    fn rejects_recordings_without_frames() {
+        let name = format!("v4l2-quick-empty-{}", ::std::process::id());
+        let path = ::std::env::temp_dir().join(name);
+        fs::write(&path, [0x00, 0x01, 0x02]).unwrap();
+        let err = Replay::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use toml;
+
+/// Settings kept in a file, as JSON if its name ends in .json and as TOML
+/// otherwise, like the profiles and presets of v4l2tcp
+pub trait SettingsFile: Serialize + DeserializeOwned {
+    fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut text = String::new();
+        try!(File::open(path.as_ref()).and_then(|mut file| file.read_to_string(&mut text)));
+        let parsed = if is_json(path.as_ref()) {
+            serde_json::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.to_string())
+        };
+        parsed.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text = if is_json(path.as_ref()) {
+            serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+        } else {
+            toml::to_string(self).map_err(|err| err.to_string())
+        };
+        let text = try!(text.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)));
+        let mut file = try!(File::create(path));
+        file.write_all(text.as_bytes())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
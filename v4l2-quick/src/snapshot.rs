@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_json;
+
+use caps::FormatCaps;
+use device::Device;
+use mock::MockCamera;
+use source::CaptureSource;
+use V4l2Result;
+
+/// Everything a camera offers, written down so the picker can be rerun
+/// against it without the camera. Load one into a `MockCamera` to search it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub driver: String,
+    pub card: String,
+    pub bus_info: String,
+    pub formats: Vec<FormatCaps>,
+}
+
+impl Snapshot {
+    /// Enumerates `source`, which has no name to record
+    pub fn of<S: CaptureSource + ?Sized>(source: &S) -> V4l2Result<Self> {
+        Ok(Snapshot {
+            formats: try!(FormatCaps::enumerate(source)),
+            .. Default::default()
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a snapshot is always valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+        file.write_all(self.to_json().as_bytes())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut json = String::new();
+        try!(File::open(path).and_then(|mut file| file.read_to_string(&mut json)));
+        Snapshot::from_json(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl From<Device> for Snapshot {
+    fn from(device: Device) -> Self {
+        Snapshot {
+            driver: device.driver,
+            card: device.card,
+            bus_info: device.bus_info,
+            formats: device.formats,
+        }
+    }
+}
+
+impl From<Snapshot> for MockCamera {
+    fn from(snapshot: Snapshot) -> Self {
+        MockCamera::new(snapshot.formats, Vec::new())
+    }
+}
@@ -0,0 +1,156 @@
+use std::io;
+use std::ops::Deref;
+
+use rscam;
+use rscam::{Camera, Config, Control, CtrlData, FormatInfo};
+
+use controls::{ControlInfo, ControlValue};
+use fourcc::FourCc;
+use frame::Frame;
+use mmap::DriverBuffer;
+use DisStepInfo;
+use V4l2Result;
+
+/// The bytes of one captured frame
+pub enum Buffer {
+    /// Still mapped from the driver, it is handed back once dropped
+    Mapped(rscam::Frame),
+    /// Dequeued from an `MmapCamera`, it is queued again once dropped
+    Dequeued(DriverBuffer),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Buffer::Mapped(ref frame) => &frame[..],
+            Buffer::Dequeued(ref buffer) => &buffer[..],
+            Buffer::Owned(ref data) => &data[..],
+        }
+    }
+}
+
+/// A format, resolution and frame interval a source was started with
+pub type Mode = (FourCc, (u32, u32), (u32, u32));
+
+/// Anything frames can be captured from, like a V4L2 `Camera`
+pub trait CaptureSource {
+    /// Every pixel format on offer
+    fn formats(&self) -> Vec<FormatInfo>;
+
+    /// The resolutions on offer for `format`
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo>;
+
+    /// The frame intervals on offer for `format` at `resolution`
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo>;
+
+    /// Start streaming with `config`
+    fn start(&mut self, config: &Config) -> V4l2Result<()>;
+
+    /// Block until the next frame is ready
+    fn capture(&mut self) -> io::Result<Frame>;
+
+    /// Every control it has, like brightness or exposure
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        Ok(Vec::new())
+    }
+
+    /// Control `id` and its current value
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        let controls = try!(self.controls());
+        controls.into_iter()
+            .find(|control| control.id == id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such control"))
+    }
+
+    /// Writes `value` to control `id` unchecked, `set_control` checks it first
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        let _ = (id, value);
+        Err(io::Error::other("controls are not supported"))
+    }
+}
+
+impl CaptureSource for Camera {
+    fn formats(&self) -> Vec<FormatInfo> {
+        Camera::formats(self).filter_map(Result::ok).collect()
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        Camera::resolutions(self, &format.0[..]).map(Into::into)
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        Camera::intervals(self, &format.0[..], resolution).map(Into::into)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        Camera::start(self, config)
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        // rscam keeps the timestamp and sequence to itself
+        let frame = try!(Camera::capture(self));
+        let (format, resolution) = (FourCc(frame.format), frame.resolution);
+        Ok(Frame::new(Buffer::Mapped(frame), format, resolution))
+    }
+
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        // Class headings are listed among the controls, but aren't any
+        Camera::controls(self)
+            .filter(|control| !matches!(*control, Ok(Control { data: CtrlData::CtrlClass, .. })))
+            .map(|control| control.map(Into::into))
+            .collect()
+    }
+
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        Camera::get_control(self, id).map(Into::into)
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        match *value {
+            ControlValue::Integer(value) => Camera::set_control(self, id, value),
+            ControlValue::Boolean(value) => Camera::set_control(self, id, value),
+            ControlValue::Press => Camera::set_control(self, id, 1),
+            // rscam hands the driver a string without its length
+            ControlValue::Text(_) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "text controls can't be written"))
+            },
+        }
+    }
+}
+
+impl<S: CaptureSource + ?Sized> CaptureSource for Box<S> {
+    fn formats(&self) -> Vec<FormatInfo> {
+        (**self).formats()
+    }
+
+    fn resolutions(&self, format: FourCc) -> V4l2Result<DisStepInfo> {
+        (**self).resolutions(format)
+    }
+
+    fn intervals(&self, format: FourCc, resolution: (u32, u32)) -> V4l2Result<DisStepInfo> {
+        (**self).intervals(format, resolution)
+    }
+
+    fn start(&mut self, config: &Config) -> V4l2Result<()> {
+        (**self).start(config)
+    }
+
+    fn capture(&mut self) -> io::Result<Frame> {
+        (**self).capture()
+    }
+
+    fn controls(&self) -> io::Result<Vec<ControlInfo>> {
+        (**self).controls()
+    }
+
+    fn control(&self, id: u32) -> io::Result<ControlInfo> {
+        (**self).control(id)
+    }
+
+    fn write_control(&mut self, id: u32, value: &ControlValue) -> io::Result<()> {
+        (**self).write_control(id, value)
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use error::{Error, Reason, Rejected, Report};
+use ffi;
+use fourcc::FourCc;
+use source::CaptureSource;
+use {search, Constraints, ConfigSummary};
+
+/// `config` the way the driver would set it up, with the stride it would
+/// use. Only the format, resolution and field can be tried without setting
+/// them; the interval
+/// is only known to stick if the driver can set intervals at all, when it
+/// can't the one it runs at is reported instead.
+fn negotiate_with(file: &File, config: &ConfigSummary, timeperframe: Option<(u32, u32)>)
+        -> io::Result<ConfigSummary> {
+    let pix = try!(ffi::try_fmt(file, config.resolution, config.format.0, config.field));
+    Ok(ConfigSummary {
+        interval: timeperframe.unwrap_or(config.interval),
+        resolution: (pix.width, pix.height),
+        format: FourCc(pix.pixelformat),
+        field: pix.field,
+        nbuffers: config.nbuffers,
+        stride: Some(pix.bytesperline),
+    })
+}
+
+/// The interval the driver is stuck at, if it can't set them. Drivers
+/// that can't even say leave it unknown.
+fn fixed_interval(file: &File) -> io::Result<Option<(u32, u32)>> {
+    let parm = match ffi::g_parm(file) {
+        Ok(parm) => parm,
+        Err(ref err) if unsupported(err) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let fixed = parm.capability & ffi::CAP_TIMEPERFRAME == 0;
+    Ok(if fixed && parm.timeperframe.0 != 0 { Some(parm.timeperframe) } else { None })
+}
+
+fn unsupported(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(ffi::ENOTTY) | Some(ffi::EINVAL))
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    // A descriptor of our own, it doesn't disturb one that is streaming
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// What the driver of the device at `path` would make of `config`
+pub fn negotiate<P: AsRef<Path>>(path: P, config: &ConfigSummary) -> io::Result<ConfigSummary> {
+    let file = try!(open(path.as_ref()));
+    let timeperframe = try!(fixed_interval(&file));
+    negotiate_with(&file, config, timeperframe)
+}
+
+/// Whether the driver would run `config` as `negotiated`. Only a format or
+/// size change counts: drivers pick the field they can do, like interlaced
+/// when progressive was asked for, and the negotiated one is started.
+fn rewritten(config: &ConfigSummary, negotiated: &ConfigSummary) -> bool {
+    config.format != negotiated.format || config.resolution != negotiated.resolution
+}
+
+/// Keeps each of `configs` in a `native` format as `negotiate` has it,
+/// unless that fails or rewrites it. Formats libv4l2 emulates are converted
+/// from a native one the driver picks, so they are kept as they are.
+fn check_each<F>(configs: Vec<ConfigSummary>, native: &[[u8; 4]], report: &mut Report,
+                 mut negotiate: F) -> Vec<ConfigSummary>
+where F: FnMut(&ConfigSummary) -> io::Result<ConfigSummary> {
+    let mut verified = Vec::with_capacity(configs.len());
+    for config in configs {
+        if !native.contains(&config.format.0) {
+            verified.push(config);
+            continue;
+        }
+        let reason = match negotiate(&config) {
+            Ok(ref negotiated) if !rewritten(&config, negotiated) => {
+                verified.push(negotiated.clone());
+                continue;
+            },
+            Ok(negotiated) => Reason::Rewritten(negotiated),
+            Err(err) => Reason::Negotiation(err.to_string()),
+        };
+        report.rejected.push(Rejected {
+            format: config.format,
+            resolution: Some(config.resolution),
+            interval: Some(config.interval),
+            reason: reason,
+        });
+    }
+    verified
+}
+
+/// Tries each of `configs` on the device at `path`, keeping them as the
+/// driver negotiates them. Those the driver would rewrite, which it
+/// advertised but can't really do, or fails to try are left out and added
+/// to `report`.
+pub fn verify<P: AsRef<Path>>(path: P, configs: Vec<ConfigSummary>, report: &mut Report)
+        -> io::Result<Vec<ConfigSummary>> {
+    let file = try!(open(path.as_ref()));
+    let timeperframe = try!(fixed_interval(&file));
+    let native = try!(ffi::enum_fmt(&file));
+    Ok(check_each(configs, &native, report, |config| {
+        negotiate_with(&file, config, timeperframe)
+    }))
+}
+
+/// Like `search`, keeping only the configurations the driver of the device
+/// at `path`, which `camera` has open, takes as they are
+pub fn search_verified<S, P>(camera: &S, path: P, reqs: Constraints)
+        -> Result<Vec<ConfigSummary>, Error>
+where S: CaptureSource + ?Sized, P: AsRef<Path> {
+    let configs = try!(search(camera, reqs));
+    let mut report = Report::default();
+    let verified = try!(verify(path, configs, &mut report));
+    if verified.is_empty() {
+        return Err(Error::NoMatch(report));
+    }
+    Ok(verified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consts::{FIELD_INTERLACED, FIELD_NONE};
+    use mock::summary;
+
+    fn config(format: FourCc, resolution: (u32, u32), field: u32) -> ConfigSummary {
+        ConfigSummary {
+            field: field,
+            .. summary(format, resolution, (1, 30))
+        }
+    }
+
+    #[test]
+    fn only_format_and_size_changes_count() {
+        let asked = config(FourCc::YUYV, (640, 480), FIELD_NONE);
+        assert!(!rewritten(&asked, &config(FourCc::YUYV, (640, 480), FIELD_INTERLACED)));
+        assert!(rewritten(&asked, &config(FourCc::YUYV, (640, 360), FIELD_NONE)));
+        assert!(rewritten(&asked, &config(FourCc::MJPG, (640, 480), FIELD_NONE)));
+    }
+    #[test]
+    fn failures_and_rewrites_are_reported_and_emulated_formats_kept() {
+        let configs = vec![
+            summary(FourCc::YUYV, (640, 480), (1, 30)),
+            summary(FourCc::YUYV, (4096, 4096), (1, 30)),
+            summary(FourCc::MJPG, (640, 480), (1, 30)),
+            // Emulated by libv4l2, so the driver has never heard of it
+            summary(FourCc::RGB3, (640, 480), (1, 30)),
+        ];
+        let mut report = Report::default();
+        let native = [FourCc::YUYV.0, FourCc::MJPG.0];
+        let verified = check_each(configs, &native, &mut report, |config| {
+            match (config.format, config.resolution) {
+                (FourCc::MJPG, _) => Ok(summary(FourCc::YUYV, (640, 480), (1, 30))),
+                (_, (4096, 4096)) => Err(io::Error::from_raw_os_error(ffi::EINVAL)),
+                _ => Ok(ConfigSummary { stride: Some(1280), .. config.clone() }),
+            }
+        });
+        let kept: Vec<_> = verified.iter().map(|config| (config.format, config.stride)).collect();
+        assert_eq!(kept, vec![(FourCc::YUYV, Some(1280)), (FourCc::RGB3, None)]);
+        assert_eq!(report.rejected.len(), 2);
+        match report.rejected[0].reason {
+            Reason::Negotiation(_) => assert_eq!(report.rejected[0].resolution, Some((4096, 4096))),
+            ref other => panic!("expected a failed negotiation, got {:?}", other),
+        }
+        match report.rejected[1].reason {
+            Reason::Rewritten(ref config) => assert_eq!(config.format, FourCc::YUYV),
+            ref other => panic!("expected a rewrite, got {:?}", other),
+        }
+        assert!(unsupported(&io::Error::from_raw_os_error(ffi::ENOTTY)));
+        assert!(!unsupported(&io::Error::from_raw_os_error(16)));
+    }
+}